@@ -1,5 +1,7 @@
 use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Not, Shr, Shl};
 
+use Geometry;
+
 // We interpret the values in the following layout:
 //  **00**01**02**03**04**05*/06\*07**08**09**0A**0B**0C****
 //  0D**0E**0F**10**11**12*/13  14\*15**16**17**18**19**1A**
@@ -21,16 +23,29 @@ use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, N
 //  E5**E6**E7**E8**E9**EA**EB**EC**ED**EE**EF**F0**F1**F2**
 //  **F3**F4**F5**F6**F7**F8**F9**FA**FB**FC**FD**FE**FF****
 
+// The square board uses 16 rows of 16 bits each, i.e. `(x, y)` is bit `16*y + x`. It fills the
+// whole bitboard, so moves are kept from wrapping around into the next or previous row by
+// masking out the files they can not reach.
+
 pub type BitIndex = u8;
 
 const LONG_ROW: u8 = 13;
 const TWO_ROWS: u8 = 27;
 
-pub fn pos_to_index(x: u8, y: u8) -> BitIndex {
-    y/2 * TWO_ROWS + (y%2)*LONG_ROW + x
+const SQUARE_ROW: u8 = 16;
+
+pub fn pos_to_index(geometry: Geometry, x: u8, y: u8) -> BitIndex {
+    match geometry {
+        Geometry::Square => y * SQUARE_ROW + x,
+        _ => y/2 * TWO_ROWS + (y%2)*LONG_ROW + x,
+    }
 }
 
-pub fn index_to_pos(index: BitIndex) -> (i8, i8) {
+pub fn index_to_pos(geometry: Geometry, index: BitIndex) -> (i8, i8) {
+    if geometry == Geometry::Square {
+        return ((index % SQUARE_ROW) as i8, (index / SQUARE_ROW) as i8);
+    }
+
     let mut index = index;
     let mut y = 0;
 
@@ -44,37 +59,18 @@ pub fn index_to_pos(index: BitIndex) -> (i8, i8) {
     (x as i8, y as i8)
 }
 
-#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
-pub struct Bitboard([u64; 4]);
+/// Every bit of the board.
+pub const ALL: Bitboard = Bitboard([!0; 4]);
 
-pub const BB_INVALID: Bitboard = Bitboard(
-    [
-    0b00000000_00111110_00011111_11111000_11111111_11100111_11111111_10111111,
-    0b00000111_10000000_00111100_00000000_11100000_00000011_00000000_00001000,
-    0b11111111_00001111_10000000_00000010_00000000_00011000_00000000_11100000,
-    0b11111111_11111111_11111111_11111111_10111111_11111100_11111111_11100011,
-    ]
-);
-
-pub const BB_TARGET: [Bitboard; 2] = [
-    Bitboard(
-        [
-        0b00000000_00000000_00000000_00000000_00000000_00000000_00000000_00000000,
-        0b00000000_00000000_00000000_00000000_00000000_00000000_00000000_00000000,
-        0b00000000_11110000_00000111_11000000_00000000_00000000_00000000_00000000,
-        0b00000000_00000000_00000000_00000000_01000000_00000011_00000000_00011100,
-        ]
-    ),
-    Bitboard(
-        [
-        0b01111100_00000001_11100000_00000111_00000000_00011000_00000000_01000000,
-        0b00000000_00000000_00000000_00000000_00000000_00000000_00000000_00000000,
-        0b00000000_00000000_00000000_00000000_00000000_00000000_00000000_00000000,
-        0b00000000_00000000_00000000_00000000_00000000_00000000_00000000_00000000,
-        ]
-    ),
-];
+// The files of the square board next to its edges, which moves to the east or west may not end
+// on.
+pub const NOT_FILE_A: Bitboard = Bitboard([!0x0001_0001_0001_0001; 4]);
+pub const NOT_FILES_AB: Bitboard = Bitboard([!0x0003_0003_0003_0003; 4]);
+pub const NOT_FILE_P: Bitboard = Bitboard([!0x8000_8000_8000_8000; 4]);
+pub const NOT_FILES_OP: Bitboard = Bitboard([!0xC000_C000_C000_C000; 4]);
 
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Bitboard([u64; 4]);
 
 impl Bitboard {
     pub fn bit(index: BitIndex) -> Self {
@@ -7,76 +7,123 @@
 //!   * How well the kinds of pieces match up with the kinds of the destination positions.
 //!   * How centralized the pieces of each player are.
 //!   * The mobility of the individual pieces.
+//!
+//! Distances are measured in rows on star boards and in king moves towards the far corner on the
+//! square board.
 
-use ::{BOARD_HEIGHT, BOARD_WIDTH, GameState, Tile};
+use ::{BOARD_HEIGHT, MAX_BOARD_WIDTH, GameState, Geometry, Tile};
 use ai::Score;
 use ai::internal_game_state::{InternalGameState, InternalMove};
 use ai::bitboard::index_to_pos;
 
-/// Approximate distance between row and end of target area.
-///
-/// In general, this `DIST_SCORE[player][row]` is twice distance between a piece of player `player`
-/// on row `row` and the end of this player's target area. To encourage a placement of the pieces on
-/// the second and fourth row of the target area (allowing better filling of the area), these rows
-/// have their distance discounted.
-const DIST_SCORE: [[Score; BOARD_HEIGHT as usize]; 2] = [
-    [32, 30, 28, 26, 24, 22, 20, 18, 16, 14, 12, 10,  8,  1,  4,  2,  0],
-    [ 0,  2,  4,  1,  8, 10, 12, 14, 16, 18, 20, 22, 24, 26, 28, 30, 32],
-];
+/// The number of stones of each player and the number of rows of the standard star for two
+/// players, which the weights of the aspects were tuned for. On other boards, the weights are
+/// scaled by the number of stones and rows there.
+const STANDARD_STONES: Score = 15;
+const STANDARD_ROWS: Score = 17;
+
+/// The distance to the end of the target area of the row whose distance is discounted.
+const DISCOUNTED_DISTANCE: i8 = 3;
 
 /// Caches properties of and evaluates the current game state.
 ///
 /// Notice that because it does only recompute what changed (except at construction time via
 /// `from`) it is necessary to call `make_move` and `unmake_move` after the game state has changed.
+#[derive(Clone)]
 pub struct Evaluation {
+    geometry: Geometry,
     target_kinds: [[i8; 4]; 2],
     kinds: [[i8; 4]; 2],
-    ys: [[i8; BOARD_HEIGHT as usize]; 2],
+    /// The number of stones of each player by their distance to the end of its target area.
+    distances: [[i8; BOARD_HEIGHT as usize]; 2],
     dist: [isize; 2],
-    dist_to_center: [[i8; BOARD_WIDTH as usize]; 2],
+    dist_to_center: [[i8; MAX_BOARD_WIDTH as usize]; 2],
+    /// `distance[player][x][y]` is the distance between `(x, y)` and the end of the target area
+    /// of `player`, in rows on star boards and in king moves on the square board.
+    distance: [[[i8; BOARD_HEIGHT as usize]; MAX_BOARD_WIDTH as usize]; 2],
+    /// `dist_score[player][x][y]` is twice `distance[player][x][y]`. To encourage a placement of
+    /// the pieces on the second and fourth row of the target area (allowing better filling of the
+    /// area), the fourth row has its distance discounted.
+    dist_score: [[[Score; BOARD_HEIGHT as usize]; MAX_BOARD_WIDTH as usize]; 2],
+    /// The number of stones of each player.
+    stones: Score,
+    /// The number of rows between the start and the end of a target area, i.e. one more than the
+    /// largest distance.
+    rows: Score,
+}
+
+/// The hole of the target area of `player` farthest from the center of the board, which all
+/// distances are measured to.
+fn tip(state: &GameState, player: u8) -> (i8, i8) {
+    state.targets(player).into_iter().max_by_key(|&(x, y)| match state.geometry() {
+        Geometry::Square => (2*x - 15).abs() + (2*y - 15).abs(),
+        _ => (y - 8).abs(),
+    }).unwrap()
 }
 
 impl<'a> From<&'a GameState> for Evaluation {
     fn from(state: &GameState) -> Self {
+        let geometry = state.geometry();
         let mut target_kinds = [[0; 4]; 2];
-        for &(x, y) in GameState::targets(0) {
-            target_kinds[0][kind(x, y)] += 1;
+        for &(x, y) in &state.targets(0) {
+            target_kinds[0][kind(geometry, x, y)] += 1;
         }
 
-        for &(x, y) in GameState::targets(1) {
-            target_kinds[1][kind(x, y)] += 1;
+        for &(x, y) in &state.targets(1) {
+            target_kinds[1][kind(geometry, x, y)] += 1;
+        }
+
+        let mut distance = [[[0; BOARD_HEIGHT as usize]; MAX_BOARD_WIDTH as usize]; 2];
+        let mut dist_score = [[[0; BOARD_HEIGHT as usize]; MAX_BOARD_WIDTH as usize]; 2];
+        let mut rows = 0;
+        for player in 0..2 {
+            let (tx, ty) = tip(state, player as u8);
+            let target_depth = state.targets(player as u8).iter().map(|&(x, y)| hole_distance(geometry, tx, ty, x, y)).max().unwrap();
+            for x in 0..MAX_BOARD_WIDTH as i8 {
+                for y in 0..BOARD_HEIGHT as i8 {
+                    if !geometry.is_valid_location(x, y) {
+                        continue;
+                    }
+
+                    let d = hole_distance(geometry, tx, ty, x, y);
+                    distance[player][x as usize][y as usize] = d;
+                    dist_score[player][x as usize][y as usize] = if d == DISCOUNTED_DISTANCE && target_depth > DISCOUNTED_DISTANCE {
+                        1
+                    } else {
+                        2 * d as Score
+                    };
+                    rows = ::std::cmp::max(rows, d as Score + 1);
+                }
+            }
         }
 
         let mut kinds = [[0; 4]; 2];
-        let mut ys = [[0; BOARD_HEIGHT as usize]; 2];
+        let mut distances = [[0; BOARD_HEIGHT as usize]; 2];
         let mut dist = [0; 2];
-        let mut dist_to_center = [[0; BOARD_WIDTH as usize]; 2];
-        for x in 0..BOARD_WIDTH as i8 {
+        let mut dist_to_center = [[0; MAX_BOARD_WIDTH as usize]; 2];
+        for x in 0..MAX_BOARD_WIDTH as i8 {
             for y in 0..BOARD_HEIGHT as i8 {
-                match state.get(x, y) {
-                    Tile::Player(0) => {
-                        kinds[0][kind(x, y)] += 1;
-                        ys[0][y as usize] += 1;
-                        dist[0] += DIST_SCORE[0][y as usize];
-                        dist_to_center[0][distance_to_center(x, y) as usize] += 1;
-                    }
-                    Tile::Player(1) => {
-                        kinds[1][kind(x, y)] += 1;
-                        ys[1][y as usize] += 1;
-                        dist[1] += DIST_SCORE[1][y as usize];
-                        dist_to_center[1][distance_to_center(x, y) as usize] += 1;
-                    }
-                    _ => {}
+                if let Tile::Player(player) = state.get(x, y) {
+                    let player = player as usize;
+                    kinds[player][kind(geometry, x, y)] += 1;
+                    distances[player][distance[player][x as usize][y as usize] as usize] += 1;
+                    dist[player] += dist_score[player][x as usize][y as usize];
+                    dist_to_center[player][distance_to_center(geometry, x, y) as usize] += 1;
                 }
             }
         }
 
         Evaluation {
+            geometry,
             target_kinds,
             kinds,
-            ys,
+            distances,
             dist_to_center,
             dist,
+            distance,
+            dist_score,
+            stones: state.home(0).len() as Score,
+            rows,
         }
     }
 }
@@ -84,16 +131,18 @@ impl<'a> From<&'a GameState> for Evaluation {
 impl Evaluation {
     /// Updates the evaluation cache for the move `mov` of player `player`.
     pub fn make_move(&mut self, player: u8, mov: InternalMove) {
-        let (fx, fy) = index_to_pos(mov.from);
-        let (tx, ty) = index_to_pos(mov.to);
-        self.kinds[player as usize][kind(fx, fy)] -= 1;
-        self.kinds[player as usize][kind(tx, ty)] += 1;
-        self.ys[player as usize][fy as usize] -= 1;
-        self.ys[player as usize][ty as usize] += 1;
-
-        self.dist[player as usize] += DIST_SCORE[player as usize][ty as usize] - DIST_SCORE[player as usize][fy as usize];
-        self.dist_to_center[player as usize][distance_to_center(fx, fy) as usize] -= 1;
-        self.dist_to_center[player as usize][distance_to_center(tx, ty) as usize] += 1;
+        let geometry = self.geometry;
+        let player = player as usize;
+        let (fx, fy) = index_to_pos(geometry, mov.from);
+        let (tx, ty) = index_to_pos(geometry, mov.to);
+        self.kinds[player][kind(geometry, fx, fy)] -= 1;
+        self.kinds[player][kind(geometry, tx, ty)] += 1;
+        self.distances[player][self.distance[player][fx as usize][fy as usize] as usize] -= 1;
+        self.distances[player][self.distance[player][tx as usize][ty as usize] as usize] += 1;
+
+        self.dist[player] += self.dist_score[player][tx as usize][ty as usize] - self.dist_score[player][fx as usize][fy as usize];
+        self.dist_to_center[player][distance_to_center(geometry, fx, fy) as usize] -= 1;
+        self.dist_to_center[player][distance_to_center(geometry, tx, ty) as usize] += 1;
     }
 
     /// Updates the evaluation cache for the reverse move `mov` of player `player`.
@@ -105,10 +154,10 @@ impl Evaluation {
     /// score.
     pub fn evaluate(&mut self, state: InternalGameState) -> Score {
         let mut score = 0;
-        score += 100_000 * self.score_dist_last_piece() / 34;
-        score += 100_000 * self.score_total_distance() / 209;
-        score += 100_000 * self.score_centralization() / 100;
-        score += 100_000 * self.score_kinds() / 120;
+        score += 100_000 * STANDARD_ROWS * self.score_dist_last_piece() / (34 * self.rows);
+        score += 100_000 * STANDARD_STONES * STANDARD_ROWS * self.score_total_distance() / (209 * self.stones * self.rows);
+        score += 100_000 * STANDARD_STONES * self.score_centralization() / (100 * self.stones);
+        score += 100_000 * STANDARD_STONES * self.score_kinds() / (120 * self.stones);
         score += self.score_mobility(state) * 2;
 
         if state.current_player == 0 {
@@ -131,8 +180,8 @@ impl Evaluation {
     }
 
     fn score_dist_last_piece(&self) -> Score {
-        let p0 = self.ys[0].iter().rev().enumerate().rev().find(|&(_dist, &count)| count > 0).unwrap().0 as isize;
-        let p1 = self.ys[1].iter().enumerate().rev().find(|&(_dist, &count)| count > 0).unwrap().0 as isize;
+        let p0 = self.distances[0].iter().rposition(|&count| count > 0).unwrap() as isize;
+        let p1 = self.distances[1].iter().rposition(|&count| count > 0).unwrap() as isize;
         p1 - p0
     }
 
@@ -149,19 +198,39 @@ impl Evaluation {
     }
 }
 
-fn distance_to_center(x: i8, y: i8) -> i8 {
-    ::std::cmp::min((6-x).abs(), (x-(6+y%2)).abs())
+/// The distance between `(x, y)` and the end `(tx, ty)` of a target area: the number of rows on
+/// star boards and the number of king moves on the square board.
+fn hole_distance(geometry: Geometry, tx: i8, ty: i8, x: i8, y: i8) -> i8 {
+    match geometry {
+        Geometry::Square => ::std::cmp::max((tx - x).abs(), (ty - y).abs()),
+        _ => (ty - y).abs(),
+    }
 }
 
-fn kind(x: i8, y: i8) -> usize {
-    (2*((x + y/2)%2) + y%2) as usize
+/// The distance of `(x, y)` to the line connecting the camps of the two players: the middle
+/// column of the star or the long diagonal of the square board.
+fn distance_to_center(geometry: Geometry, x: i8, y: i8) -> i8 {
+    match geometry {
+        Geometry::Square => (x - y).abs(),
+        _ => ::std::cmp::min((6-x).abs(), (x-(6+y%2)).abs()),
+    }
+}
+
+/// The class of holes reachable from `(x, y)` by jumps alone.
+fn kind(geometry: Geometry, x: i8, y: i8) -> usize {
+    match geometry {
+        Geometry::Square => (2*(x%2) + y%2) as usize,
+        _ => (2*((x + y/2)%2) + y%2) as usize,
+    }
 }
 
 mod tests {
     #[test]
     fn test_kinds() {
+        use Geometry;
         use ai::evaluation::kind;
 
+        let kind = |x, y| kind(Geometry::Star, x, y);
         let x = 6;
         let y = 9;
 
@@ -177,5 +246,28 @@ mod tests {
         assert_ne!(kind(x, y), kind(x+1, y+1));
         assert_ne!(kind(x, y), kind(x, y+1));
     }
-}
 
+    #[test]
+    fn test_geometry_tables() {
+        use {GameState, Geometry};
+        use ai::evaluation::Evaluation;
+
+        // The tables of the standard star are the ones the weights were tuned with.
+        let evaluation = Evaluation::from(&GameState::default());
+        assert_eq!((evaluation.stones, evaluation.rows), (15, 17));
+        assert_eq!((0..17).map(|y| evaluation.dist_score[0][6][y]).collect::<Vec<_>>(),
+                   vec![32, 30, 28, 26, 24, 22, 20, 18, 16, 14, 12, 10, 8, 1, 4, 2, 0]);
+        assert_eq!(evaluation.dist_score[1][6][3], 1);
+
+        let evaluation = Evaluation::from(&GameState::with_geometry(Geometry::SmallStar { size: 3 }, 2));
+        assert_eq!((evaluation.stones, evaluation.rows), (6, 13));
+        assert_eq!(evaluation.dist_score[0][6][4], 2 * 10);
+
+        let evaluation = Evaluation::from(&GameState::with_geometry(Geometry::Square, 2));
+        assert_eq!((evaluation.stones, evaluation.rows), (19, 16));
+        assert_eq!(evaluation.distance[0][15][15], 0);
+        assert_eq!(evaluation.distance[0][0][0], 15);
+        assert_eq!(evaluation.distance[1][15][0], 15);
+        assert_eq!(evaluation.dist_score[1][3][1], 1);
+    }
+}
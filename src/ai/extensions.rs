@@ -0,0 +1,117 @@
+//! Search extensions and reductions by the character of a move.
+//!
+//! Every move costs one ply of depth, adjusted by the amounts below, which are given in the
+//! thousandths of a ply the search counts its depth in. Positive amounts extend the search
+//! after a move, negative ones reduce it. This spends the search effort on the lines which
+//! decide the race between the two armies.
+//!
+//! By default, only moves into the target area are extended, by a quarter ply. The `ai` binary
+//! switches the extensions off with the `extensions off` command, and against itself without them
+//! the engine won 18 games and lost 6 in
+//!
+//! ```text
+//! match --rounds 12 --time-control 5+0.1 extended target/release/ai plain target/release/ai --send "extensions off"
+//! ```
+//!
+//! Also extending long jump chains by a quarter ply and reducing sideways moves by a quarter and
+//! backward moves by half a ply won only 1 game and lost 23 in the same match, so those amounts
+//! default to 0.
+
+use ai::Depth;
+use ai::ONE_PLY;
+use ai::internal_game_state::{InternalGameState, InternalMove};
+
+/// A move advancing at least this many rows is a long jump chain. On the square board, a move
+/// advances by the sum of the files and ranks it goes towards the target corner.
+const LONG_JUMP_ROWS: i8 = 4;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Extensions {
+    /// For a move bringing a stone into the target area.
+    pub target_entry: Depth,
+    /// For a jump chain advancing the stone by at least four rows.
+    pub long_jump: Depth,
+    /// For a move staying in the same row.
+    pub sideways: Depth,
+    /// For a move going back towards the own home.
+    pub backward: Depth,
+}
+
+impl Default for Extensions {
+    fn default() -> Extensions {
+        Extensions {
+            target_entry: ONE_PLY / 4,
+            long_jump: 0,
+            sideways: 0,
+            backward: 0,
+        }
+    }
+}
+
+impl Extensions {
+    /// No extensions or reductions at all, so every move costs exactly one ply.
+    pub fn none() -> Extensions {
+        Extensions {
+            target_entry: 0,
+            long_jump: 0,
+            sideways: 0,
+            backward: 0,
+        }
+    }
+
+    /// The extension of `mov` in `state`, before the move is made. The total is between minus
+    /// and plus half a ply, so every move still costs some depth and the search ends, and no move
+    /// costs more than one and a half plies.
+    pub fn extension(&self, state: &InternalGameState, mov: InternalMove) -> Depth {
+        let player = state.current_player as usize;
+        let rows = state.progress(state.current_player, mov.to) - state.progress(state.current_player, mov.from);
+
+        let mut extension = 0;
+        if state.targets[player].get_bit(mov.to) && !state.targets[player].get_bit(mov.from) {
+            extension += self.target_entry;
+        }
+        if rows >= LONG_JUMP_ROWS {
+            extension += self.long_jump;
+        } else if rows == 0 {
+            extension += self.sideways;
+        } else if rows < 0 {
+            extension += self.backward;
+        }
+
+        extension.clamp(-ONE_PLY / 2, ONE_PLY / 2)
+    }
+}
+
+mod tests {
+    #[test]
+    fn test_extensions() {
+        use {GameState, Geometry, Move};
+        use ai::ONE_PLY;
+        use ai::extensions::Extensions;
+        use ai::internal_game_state::{InternalGameState, InternalMove};
+        use builder::PositionBuilder;
+
+        let mut builder = PositionBuilder::new(Default::default(), 2);
+        builder.place(6, 11, 0).place(6, 5, 0).place(6, 6, 1).place(6, 8, 1);
+        let state = InternalGameState::from(builder.build().unwrap());
+        let extensions = Extensions {
+            target_entry: ONE_PLY / 2,
+            long_jump: ONE_PLY / 4,
+            sideways: -ONE_PLY / 4,
+            backward: -ONE_PLY / 2,
+        };
+        let extension = |from, to| extensions.extension(&state, InternalMove::from_move(Geometry::Star, Move { from, to }));
+
+        assert_eq!(extension((6, 11), (6, 12)), ONE_PLY / 2);
+        assert_eq!(extension((6, 5), (6, 9)), ONE_PLY / 4);
+        assert_eq!(extension((6, 5), (7, 6)), 0);
+        assert_eq!(extension((6, 5), (5, 5)), -ONE_PLY / 4);
+        assert_eq!(extension((6, 5), (6, 4)), -ONE_PLY / 2);
+
+        assert_eq!(Extensions { long_jump: ONE_PLY, ..extensions }.extension(&state, InternalMove::from_move(Geometry::Star, Move { from: (6, 11), to: (6, 15) })), ONE_PLY / 2);
+        assert_eq!(Extensions { backward: -2 * ONE_PLY, ..extensions }.extension(&state, InternalMove::from_move(Geometry::Star, Move { from: (6, 5), to: (6, 4) })), -ONE_PLY / 2);
+
+        let state = InternalGameState::from(GameState::default());
+        assert_eq!(Extensions::none().extension(&state, InternalMove::from_move(Geometry::Star, Move { from: (6, 3), to: (6, 4) })), 0);
+    }
+}
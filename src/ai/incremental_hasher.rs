@@ -1,34 +1,91 @@
-use ai::internal_game_state::InternalMove;
+use WinRule;
+use ai::internal_game_state::{InternalGameState, InternalMove};
 
-pub type IncrementalHash = usize;
+/// The hash of a position. It has 64 bits on every platform, so the hashes and with them the
+/// searches of the AI are the same on 32-bit and 64-bit machines.
+pub type IncrementalHash = u64;
 
 #[derive(Copy, Clone)]
 pub struct IncrementalHasher {
     tile_hashes: [(IncrementalHash, IncrementalHash); 256],
     to_move_hash: IncrementalHash,
+    plies_left_hash: u64,
 }
 
 impl Default for IncrementalHasher {
+    /// Creates a hasher with random keys.
     fn default() -> Self {
         use rand::Rng;
-        let mut rng = ::rand::thread_rng();
+        IncrementalHasher::with_seed(::rand::thread_rng().gen())
+    }
+}
 
-        let mut tile_hashes = [(0, 0); 256];
+/// Returns the next number of the SplitMix64 sequence. Unlike the generators of `rand`, its
+/// output is specified, so it is the same for every version and platform.
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
 
-        for i in 0..256 {
-            tile_hashes[i] = (rng.gen(), rng.gen());
+impl IncrementalHasher {
+    /// Creates a hasher whose keys are derived from `seed`, so the hashes and with them the
+    /// searches of the AI can be reproduced.
+    pub fn with_seed(seed: u64) -> Self {
+        let mut state = seed;
+        let mut tile_hashes = [(0, 0); 256];
+        for tile_hash in tile_hashes.iter_mut() {
+            *tile_hash = (splitmix64(&mut state), splitmix64(&mut state));
         }
 
-        let to_move_hash = rng.gen();
-
         IncrementalHasher {
             tile_hashes,
-            to_move_hash,
+            to_move_hash: splitmix64(&mut state),
+            plies_left_hash: splitmix64(&mut state),
+        }
+    }
+
+    /// Computes the hash of `state` from scratch.
+    ///
+    /// Starting from this hash, `update` keeps it up to date while moves are made and unmade.
+    pub fn hash(&self, state: &InternalGameState) -> IncrementalHash {
+        let mut hash = 0;
+        for index in state.pieces[0].ones() {
+            hash ^= self.tile_hashes[index as usize].0;
+        }
+        for index in state.pieces[1].ones() {
+            hash ^= self.tile_hashes[index as usize].1;
+        }
+
+        if state.current_player == 1 {
+            hash ^= self.to_move_hash;
+        }
+
+        hash
+    }
+
+    /// Returns the key of `state` in the transposition table, given its `hash`.
+    ///
+    /// Under win rules with a move limit, the score of a position depends on the plies left
+    /// until the limit, so the key also depends on them. Past the limit and under the other
+    /// rules it is the hash itself.
+    pub fn tt_key(&self, state: &InternalGameState, hash: IncrementalHash) -> IncrementalHash {
+        let limit = match state.win_rule {
+            WinRule::NoParking { ply } | WinRule::PartialFill { ply } => ply,
+            WinRule::OneStone | WinRule::AllStones => return hash,
+        };
+
+        match limit.checked_sub(state.ply as usize) {
+            Some(plies_left) if plies_left > 0 => {
+                let mut key = self.plies_left_hash ^ plies_left as u64;
+                hash ^ splitmix64(&mut key)
+            }
+            _ => hash,
         }
     }
-}
 
-impl IncrementalHasher {
     pub fn update(&self, current_player: u8, mov: InternalMove) -> IncrementalHash {
         let from;
         let to;
@@ -43,3 +100,97 @@ impl IncrementalHasher {
         from ^ to ^ self.to_move_hash
     }
 }
+
+mod tests {
+    #[test]
+    fn test_hash_from_scratch() {
+        use GameState;
+        use ai::incremental_hasher::IncrementalHasher;
+        use ai::internal_game_state::InternalGameState;
+        use builder::PositionBuilder;
+
+        let hasher = IncrementalHasher::default();
+        let mut state = InternalGameState::from(GameState::default());
+        let mut hash = hasher.hash(&state);
+        let mut moves = Vec::new();
+        for i in 0..20 {
+            let possible_moves = state.possible_moves();
+            let mov = possible_moves[i * 7 % possible_moves.len()];
+            hash ^= hasher.update(state.current_player, mov);
+            state.make_move(mov);
+            assert_eq!(hash, hasher.hash(&state));
+            moves.push(mov);
+        }
+
+        while let Some(mov) = moves.pop() {
+            state.unmake_move(mov);
+            hash ^= hasher.update(state.current_player, mov.inverse());
+            assert_eq!(hash, hasher.hash(&state));
+        }
+
+        assert_eq!(hash, hasher.hash(&InternalGameState::from(GameState::default())));
+        let other_side = PositionBuilder::from_state(&GameState::default()).current_player(1).build().unwrap();
+        assert!(hash != hasher.hash(&InternalGameState::from(other_side)));
+    }
+
+    #[test]
+    fn test_seed() {
+        use GameState;
+        use ai::incremental_hasher::IncrementalHasher;
+        use ai::internal_game_state::InternalGameState;
+
+        let state = InternalGameState::from(GameState::default());
+        assert_eq!(IncrementalHasher::with_seed(1).hash(&state), IncrementalHasher::with_seed(1).hash(&state));
+        assert!(IncrementalHasher::with_seed(1).hash(&state) != IncrementalHasher::with_seed(2).hash(&state));
+
+        // The keys must not depend on the platform, the lower 32 bits of the first one are the
+        // first output of SplitMix64 for seed 0.
+        assert_eq!(IncrementalHasher::with_seed(0).tile_hashes[0].0 & 0xFFFF_FFFF, 0x7B1D_CDAF);
+    }
+
+    #[test]
+    fn test_ai_hash() {
+        use {Game, GameState};
+        use ai::AI;
+
+        let mut game = Game::default();
+        for _ in 0..6 {
+            let mov = game.state().possible_moves()[0];
+            game.move_piece(mov);
+        }
+
+        // The AI starts with the hash of the position it is created for, not the one of the start
+        // position.
+        let other = AI::new(*game.state());
+        assert_eq!(other.hash(), other.hash_position(game.state()));
+        assert!(other.hash() != other.hash_position(&GameState::default()));
+
+        let mut ai = AI::new(GameState::default());
+        for &mov in game.moves() {
+            ai.make_move(mov);
+        }
+        assert_eq!(ai.hash(), ai.hash_position(game.state()));
+    }
+
+    #[test]
+    fn test_tt_key() {
+        use {GameState, WinRule};
+        use ai::incremental_hasher::IncrementalHasher;
+        use ai::internal_game_state::InternalGameState;
+
+        let hasher = IncrementalHasher::with_seed(1);
+        let mut state = InternalGameState::from(GameState::default());
+        let hash = hasher.hash(&state);
+        let mut later = state;
+        later.ply = 10;
+        assert_eq!(hasher.tt_key(&state, hash), hash);
+        assert_eq!(hasher.tt_key(&later, hash), hash);
+
+        state.win_rule = WinRule::PartialFill { ply: 10 };
+        later.win_rule = state.win_rule;
+        assert!(hasher.tt_key(&state, hash) != hash);
+        assert_eq!(hasher.tt_key(&later, hash), hash);
+        later.ply = 2;
+        assert!(hasher.tt_key(&state, hash) != hasher.tt_key(&later, hash));
+    }
+}
@@ -1,5 +1,5 @@
-use ::{BOARD_HEIGHT, BOARD_WIDTH, GameState, Move, Tile};
-use ai::bitboard::{BB_INVALID, BB_TARGET, Bitboard, BitIndex, pos_to_index, index_to_pos};
+use ::{BOARD_HEIGHT, MAX_BOARD_WIDTH, GameState, Geometry, Move, Tile, WinRule};
+use ai::bitboard::{ALL, Bitboard, BitIndex, NOT_FILE_A, NOT_FILE_P, NOT_FILES_AB, NOT_FILES_OP, pos_to_index, index_to_pos};
 
 pub type Ply = u32;
 
@@ -8,13 +8,14 @@ pub struct InternalGameState {
     pub pieces: [Bitboard; 2],
     pub ply: Ply,
     pub current_player: u8,
+    pub win_rule: WinRule,
+    pub geometry: Geometry,
+    /// The holes of the board.
+    pub valid: Bitboard,
+    pub targets: [Bitboard; 2],
 }
 
 impl InternalGameState {
-    fn is_valid_location(index: BitIndex) -> bool {
-        !BB_INVALID.get_bit(index)
-    }
-
     fn empty_bb(&self) -> Bitboard {
         !self.occupied_bb()
     }
@@ -24,15 +25,65 @@ impl InternalGameState {
     }
 
     pub fn won(&self, player: u8) -> bool {
-        (self.pieces[0] | self.pieces[1]) & BB_TARGET[player as usize] == BB_TARGET[player as usize] && !(self.pieces[player as usize] & BB_TARGET[player as usize]).is_empty()
+        match self.win_rule {
+            WinRule::OneStone => self.filled(player),
+            WinRule::AllStones => self.pieces[player as usize] & self.targets[player as usize] == self.targets[player as usize],
+            WinRule::NoParking { ply } => {
+                self.filled(player) || (self.ply as usize >= ply && !self.parked(player) && self.parked(1-player))
+            }
+            WinRule::PartialFill { ply } => {
+                self.filled(player) || (self.ply as usize >= ply && self.stones_in_target(player) > self.stones_in_target(1-player))
+            }
+        }
+    }
+
+    /// Whether the game ended without a winner because of the win rule.
+    pub fn drawn(&self) -> bool {
+        match self.win_rule {
+            WinRule::NoParking { ply } => {
+                self.ply as usize >= ply && self.parked(0) && self.parked(1) && !self.won(0) && !self.won(1)
+            }
+            WinRule::PartialFill { ply } => self.ply as usize >= ply && !self.won(0) && !self.won(1),
+            _ => false,
+        }
+    }
+
+    fn filled(&self, player: u8) -> bool {
+        self.occupied_bb() & self.targets[player as usize] == self.targets[player as usize] && !(self.pieces[player as usize] & self.targets[player as usize]).is_empty()
+    }
+
+    fn stones_in_target(&self, player: u8) -> usize {
+        (self.pieces[player as usize] & self.targets[player as usize]).popcount()
+    }
+
+    /// Whether `player` still has stones in its home, which is the target area of the opponent.
+    fn parked(&self, player: u8) -> bool {
+        !(self.pieces[player as usize] & self.targets[1-player as usize]).is_empty()
+    }
+
+    /// How far `index` is advanced as seen by `player`: its row on star boards, where player 0
+    /// advances towards higher rows, and the sum of its file and rank on the square board, where
+    /// player 0 advances towards the lower right corner.
+    pub fn progress(&self, player: u8, index: BitIndex) -> i8 {
+        let (x, y) = index_to_pos(self.geometry, index);
+        let progress = match self.geometry {
+            Geometry::Square => x + y,
+            _ => y,
+        };
+
+        if player == 0 { progress } else { -progress }
     }
 
     pub fn reachable_from(&self, from: BitIndex) -> Bitboard {
+        if self.geometry == Geometry::Square {
+            return self.reachable_from_square(from);
+        }
+
         let mut jumping_targets = Bitboard::default();
         let mut next_jumping_targets = Bitboard::bit(from);
 
         let occupied = self.occupied_bb();
-        let empty = !BB_INVALID & self.empty_bb();
+        let empty = self.valid & self.empty_bb();
 
         while jumping_targets != next_jumping_targets {
             jumping_targets = next_jumping_targets;
@@ -73,6 +124,50 @@ impl InternalGameState {
         jumping_targets & empty
     }
 
+    /// Like `reachable_from`, but for the square board. It has no invalid bits around it, so the
+    /// files a move can not end on are masked out to keep it from wrapping around to the next or
+    /// previous row.
+    fn reachable_from_square(&self, from: BitIndex) -> Bitboard {
+        let mut jumping_targets = Bitboard::default();
+        let mut next_jumping_targets = Bitboard::bit(from);
+
+        let occupied = self.occupied_bb();
+        let empty = self.empty_bb();
+
+        while jumping_targets != next_jumping_targets {
+            jumping_targets = next_jumping_targets;
+
+            // shift left
+            for &(skip, jump, mask) in &[
+                ( 1,  2, NOT_FILES_AB), // east
+                (15, 30, NOT_FILES_OP), // south west
+                (16, 32, ALL),          // south
+                (17, 34, NOT_FILES_AB), // south east
+            ] {
+                next_jumping_targets |= (occupied << skip) & (jumping_targets << jump) & mask;
+            }
+
+            // shift right
+            for &(skip, jump, mask) in &[
+                ( 1,  2, NOT_FILES_OP), // west
+                (15, 30, NOT_FILES_AB), // north east
+                (16, 32, ALL),          // north
+                (17, 34, NOT_FILES_OP), // north west
+            ] {
+                next_jumping_targets |= (occupied >> skip) & (jumping_targets >> jump) & mask;
+            }
+
+            next_jumping_targets &= empty;
+        }
+
+        let stone = Bitboard::bit(from);
+        jumping_targets |= ((stone << 1) | (stone << 17) | (stone >> 15)) & NOT_FILE_A;
+        jumping_targets |= ((stone >> 1) | (stone >> 17) | (stone << 15)) & NOT_FILE_P;
+        jumping_targets |= (stone << 16) | (stone >> 16);
+
+        jumping_targets & empty
+    }
+
     pub fn possible_moves(&self) -> Vec<InternalMove> {
         let board = self.pieces[self.current_player as usize];
         let mut result = Vec::with_capacity(256);
@@ -84,13 +179,22 @@ impl InternalGameState {
         result
     }
 
+    /// Whether `mov` is a legal move of the player to move. Moves from the transposition table
+    /// have to be checked, since they may belong to another position which hashed to the same
+    /// entry.
+    pub fn is_legal(&self, mov: InternalMove) -> bool {
+        self.pieces[self.current_player as usize].get_bit(mov.from) && self.reachable_from(mov.from).get_bit(mov.to)
+    }
+
     pub fn make_move(&mut self, mov: InternalMove) {
         self.pieces[self.current_player as usize].set_bit(mov.to);
         self.pieces[self.current_player as usize].unset_bit(mov.from);
         self.current_player = 1-self.current_player;
+        self.ply += 1;
     }
 
     pub fn unmake_move(&mut self, mov: InternalMove) {
+        self.ply -= 1;
         self.current_player = 1-self.current_player;
         self.pieces[self.current_player as usize].set_bit(mov.from);
         self.pieces[self.current_player as usize].unset_bit(mov.to);
@@ -111,44 +215,55 @@ impl InternalMove {
         }
     }
 
-    pub fn to_move(&self) -> Move {
-        Move {
-            from: index_to_pos(self.from),
-            to: index_to_pos(self.to),
-        }
-    }
-}
-
-impl From<Move> for InternalMove {
-    fn from(mov: Move) -> InternalMove {
-        let from = pos_to_index(mov.from.0 as u8, mov.from.1 as u8);
-        let to = pos_to_index(mov.to.0 as u8, mov.to.1 as u8);
+    /// Converts a move on the board `geometry` to the bit indices of its holes.
+    pub fn from_move(geometry: Geometry, mov: Move) -> InternalMove {
+        let from = pos_to_index(geometry, mov.from.0 as u8, mov.from.1 as u8);
+        let to = pos_to_index(geometry, mov.to.0 as u8, mov.to.1 as u8);
         InternalMove {
             from,
             to,
         }
     }
+
+    pub fn to_move(self, geometry: Geometry) -> Move {
+        Move {
+            from: index_to_pos(geometry, self.from),
+            to: index_to_pos(geometry, self.to),
+        }
+    }
 }
 
 impl From<GameState> for InternalGameState {
     fn from(state: GameState) -> Self {
         let mut pieces: [Bitboard; 2] = Default::default();
+        let mut valid = Bitboard::default();
+        let mut targets: [Bitboard; 2] = Default::default();
 
-        for x in 0..BOARD_WIDTH {
+        for x in 0..MAX_BOARD_WIDTH {
             for y in 0..BOARD_HEIGHT {
-                if let Tile::Player(player) = state.get(x as i8, y as i8) {
-                    pieces[player as usize].set_bit(pos_to_index(x, y));
+                match state.get(x as i8, y as i8) {
+                    Tile::Invalid => continue,
+                    Tile::Player(player) => pieces[player as usize].set_bit(pos_to_index(state.geometry, x, y)),
+                    Tile::Empty => {}
                 }
+                valid.set_bit(pos_to_index(state.geometry, x, y));
             }
         }
 
-        assert!((pieces[0] & BB_INVALID).is_empty());
-        assert!((pieces[1] & BB_INVALID).is_empty());
+        for player in 0..2 {
+            for &(x, y) in &state.targets(player) {
+                targets[player as usize].set_bit(pos_to_index(state.geometry, x as u8, y as u8));
+            }
+        }
 
         InternalGameState {
             pieces,
             ply: state.ply as Ply,
             current_player: state.current_player,
+            win_rule: state.win_rule,
+            geometry: state.geometry,
+            valid,
+            targets,
         }
     }
 }
@@ -156,13 +271,69 @@ impl From<GameState> for InternalGameState {
 mod tests{
     #[test]
     fn test_pos_to_index() {
-        use ai::internal_game_state::pos_to_index;
-        assert_eq!(pos_to_index(6, 0), 0x06);
-        assert_eq!(pos_to_index(6, 1), 0x13);
-        assert_eq!(pos_to_index(7, 1), 0x14);
-        assert_eq!(pos_to_index(5, 2), 0x20);
-        assert_eq!(pos_to_index(6, 2), 0x21);
-        assert_eq!(pos_to_index(7, 2), 0x22);
-        assert_eq!(pos_to_index(6, 16), 0xDE);
+        use Geometry;
+        use ai::internal_game_state::{index_to_pos, pos_to_index};
+        assert_eq!(pos_to_index(Geometry::Star, 6, 0), 0x06);
+        assert_eq!(pos_to_index(Geometry::Star, 6, 1), 0x13);
+        assert_eq!(pos_to_index(Geometry::Star, 7, 1), 0x14);
+        assert_eq!(pos_to_index(Geometry::Star, 5, 2), 0x20);
+        assert_eq!(pos_to_index(Geometry::Star, 6, 2), 0x21);
+        assert_eq!(pos_to_index(Geometry::Star, 7, 2), 0x22);
+        assert_eq!(pos_to_index(Geometry::Star, 6, 16), 0xDE);
+        assert_eq!(pos_to_index(Geometry::Square, 15, 15), 0xFF);
+        assert_eq!(index_to_pos(Geometry::Square, 0x21), (1, 2));
+    }
+
+    #[test]
+    fn test_geometry_masks() {
+        use {GameState, Geometry};
+        use ai::internal_game_state::InternalGameState;
+
+        let state = InternalGameState::from(GameState::default());
+        assert_eq!(state.valid.popcount(), 121);
+        assert_eq!(state.targets[0].popcount(), 15);
+        assert_eq!(state.targets[0] & state.pieces[1], state.targets[0]);
+
+        let state = InternalGameState::from(GameState::with_geometry(Geometry::SmallStar { size: 3 }, 2));
+        assert_eq!(state.valid.popcount(), 73);
+        assert_eq!(state.targets[1].popcount(), 6);
+    }
+
+    #[test]
+    fn test_no_parking_draw() {
+        use {GameState, WinRule};
+        use ai::internal_game_state::InternalGameState;
+
+        // Both players are still parked once the limit is reached.
+        let mut state = GameState::default();
+        state.set_win_rule(WinRule::NoParking { ply: 0 });
+        let internal = InternalGameState::from(state);
+        assert!(!internal.won(0) && !internal.won(1));
+        assert!(internal.drawn());
+
+        state.set_win_rule(WinRule::NoParking { ply: 1 });
+        assert!(!InternalGameState::from(state).drawn());
+    }
+
+    #[test]
+    fn test_possible_moves() {
+        use {GameState, Geometry};
+        use ai::internal_game_state::InternalGameState;
+
+        // The moves of the bitboards match the ones of the board, also on the edges of the square
+        // board, where shifting the bitboard wraps around to the next row.
+        for &geometry in &[Geometry::Star, Geometry::SmallStar { size: 3 }, Geometry::Square] {
+            let mut state = GameState::with_geometry(geometry, 2);
+            for i in 0..40 {
+                let internal = InternalGameState::from(state);
+                let mut moves: Vec<_> = internal.possible_moves().iter().map(|mov| mov.to_move(geometry)).collect();
+                let mut expected = state.possible_moves();
+                moves.sort();
+                expected.sort();
+                assert_eq!(moves, expected);
+
+                state.move_piece(expected[i * 7 % expected.len()]);
+            }
+        }
     }
 }
@@ -1,40 +1,154 @@
 use {GameState, Move};
+use clock::Clock;
 mod bitboard;
 pub mod evaluation;
+pub mod extensions;
 mod incremental_hasher;
 mod internal_game_state;
 mod move_picker;
+pub mod pruning;
+mod race;
+pub mod statistics;
 mod tt;
 
-use std::cell::RefCell;
-use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
 
 use self::evaluation::Evaluation;
+use self::extensions::Extensions;
 use self::incremental_hasher::*;
 use self::internal_game_state::*;
 use self::move_picker::*;
+use self::pruning::Pruning;
+use self::race::RaceSolver;
+use self::statistics::{Line, Progress, Statistics};
 use self::tt::*;
 
 #[derive(Copy, Clone, PartialEq, Eq)]
 pub enum StopCondition {
     Depth(Depth),
     Time(::std::time::Duration),
+    /// Stops after visiting about this many nodes. Unlike `Time`, it gives the same result on
+    /// every run and machine.
+    Nodes(usize),
 }
 
+impl StopCondition {
+    /// Spends an equal share of the remaining time of `player` on each of its moves until the
+    /// next period, assuming 30 more moves if the whole game is a single period. The increment
+    /// and delay are used up as well, but never more than half of the remaining time.
+    pub fn for_clock(clock: &Clock, player: u8) -> StopCondition {
+        let time_control = clock.time_control();
+        let remaining = clock.remaining(player);
+        let moves_to_go = clock.moves_to_go(player).unwrap_or(30) as u32;
+        let budget = remaining / moves_to_go + time_control.increment + time_control.delay;
+        StopCondition::Time(::std::cmp::min(budget, remaining / 2))
+    }
+}
+
+/// A function receiving a report after every finished iteration of a search.
+pub type ProgressCallback = Box<dyn FnMut(&Progress) + Send>;
+
 type Score = isize;
 const WINNING_SCORE: Score = 1_000_000_000;
 
-type Depth = i32;
-const ONE_PLY: Depth = 1000;
+/// Wins are scored `WINNING_SCORE` minus the plies to the win, so scores beyond this bound
+/// are forced wins or losses.
+const WIN_BOUND: Score = WINNING_SCORE - 10_000;
+
+/// The half-width of the first aspiration window around the expected score of an iteration.
+const ASPIRATION_WINDOW: Score = 8000;
+
+/// The number of plies to a forced win if `score` is one, negative for a forced loss.
+pub fn plies_to_win(score: isize) -> Option<isize> {
+    if score > WIN_BOUND {
+        Some(WINNING_SCORE - score)
+    } else if score < -WIN_BOUND {
+        Some(-WINNING_SCORE - score)
+    } else {
+        None
+    }
+}
+
+/// Converts a win score, which counts the plies from the root, to one counting the plies from
+/// the position at `ply`, which is how it is stored in the transposition table.
+fn score_to_tt(score: Score, ply: Ply) -> Score {
+    if score > WIN_BOUND {
+        score + ply as Score
+    } else if score < -WIN_BOUND {
+        score - ply as Score
+    } else {
+        score
+    }
+}
+
+/// Converts a win score from the transposition table back to one counting the plies from the
+/// root, for the position at `ply`.
+fn score_from_tt(score: Score, ply: Ply) -> Score {
+    if score > WIN_BOUND {
+        score - ply as Score
+    } else if score < -WIN_BOUND {
+        score + ply as Score
+    } else {
+        score
+    }
+}
+
+pub type Depth = i32;
+pub const ONE_PLY: Depth = 1000;
+
+/// The depth up to which helper threads deepen their searches.
+const MAX_DEPTH: Depth = 128;
+
+/// A search in the background on the position after the expected reply of the opponent.
+struct Ponder {
+    /// The hash of the position searched.
+    hash: IncrementalHash,
+    reply: InternalMove,
+    stop_signal: Arc<AtomicBool>,
+    handle: thread::JoinHandle<usize>,
+}
 
 pub struct AI {
     pub state: InternalGameState,
-    pub print_statistics: bool,
     pub stop_condition: StopCondition,
+    /// Clears the transposition table before every search, so the move calculated only depends
+    /// on the position, the seed and the stop condition, not on earlier searches. Together with
+    /// `set_seed` and a stop condition other than `Time` searches are reproducible. Deterministic
+    /// searches are always single-threaded.
+    pub deterministic: bool,
+    /// The number of best moves searched for, each with its own score and line. Every line
+    /// after the first costs a search of the root position excluding the moves found before.
+    pub multi_pv: usize,
+    /// How much deeper or shallower the search goes after moves of different character.
+    pub extensions: Extensions,
+    /// How the null-window search reduces late moves and prunes nodes close to the leaves.
+    pub pruning: Pruning,
+    /// The number of threads searching in parallel. All but one are helpers, which search the
+    /// same position and only contribute through the shared transposition table (Lazy SMP).
+    pub threads: usize,
     stop_condition_triggered: bool,
+    /// Tells the helper threads to stop once the main search finished.
+    stop_signal: Arc<AtomicBool>,
     start: ::std::time::Instant,
-    main_tt: Rc<RefCell<TranspositionTable>>,
+    main_tt: Arc<TranspositionTable>,
+    /// The best move at the root found by the current iteration.
+    root_move: Option<InternalMove>,
+    /// The triangular table of principal variations: the line expected from every ply of the
+    /// current search path on.
+    pv_table: Vec<Vec<InternalMove>>,
+    /// The best lines of the last finished iteration.
+    lines: Vec<Line>,
+    /// Root moves the current search skips, because lines starting with them were found already.
+    excluded_root_moves: Vec<InternalMove>,
+    ponder: Option<Ponder>,
+    /// Called with a report after every finished iteration.
+    progress: Option<ProgressCallback>,
+    statistics: Statistics,
     evaluation: Evaluation,
+    /// Bounds the moves both players need once the game became a race.
+    race: RaceSolver,
     visited_nodes: usize,
     visited_leaf_nodes: usize,
     cutoffs: usize,
@@ -42,21 +156,53 @@ pub struct AI {
     tt_hits: usize,
     pv_nullsearches: usize,
     pv_failed_nullsearches: usize,
+    aspiration_researches: usize,
+    reduction_researches: usize,
+    futility_prunes: usize,
+    races: usize,
+
+    /// Killer moves, history and counter moves of this thread's searches.
+    ordering: MoveOrdering,
+    /// The moves leading to the current position, in the order they were made.
+    moves_made: Vec<InternalMove>,
+
+    /// The ply of the position the current search started from. Entries of the transposition
+    /// table are aged by it.
+    search_ply: Ply,
 
     hasher: IncrementalHasher,
     hash: IncrementalHash
 }
 
 impl AI {
+    /// Creates an AI for the position `state`.
+    ///
+    /// Panics if `state` is not a two-player game, which is the only kind the AI plays.
     pub fn new(state: GameState) -> AI {
+        assert_eq!(state.num_players(), 2, "The AI only supports two-player games");
+        let hasher = IncrementalHasher::default();
+        let internal_state = InternalGameState::from(state);
         AI {
-            state: InternalGameState::from(state),
-            print_statistics: false,
+            state: internal_state,
             stop_condition: StopCondition::Depth(0),
+            deterministic: false,
+            multi_pv: 1,
+            extensions: Extensions::default(),
+            pruning: Pruning::default(),
+            threads: 1,
             stop_condition_triggered: false,
+            stop_signal: Arc::new(AtomicBool::new(false)),
             start: ::std::time::Instant::now(),
             evaluation: Evaluation::from(&state),
-            main_tt: Rc::new(RefCell::new(TranspositionTable::new(20))),
+            race: RaceSolver::new(),
+            main_tt: Arc::new(TranspositionTable::new(20)),
+            root_move: None,
+            pv_table: Vec::new(),
+            lines: Vec::new(),
+            excluded_root_moves: Vec::new(),
+            ponder: None,
+            progress: None,
+            statistics: Statistics::default(),
             visited_nodes: 0,
             visited_leaf_nodes: 0,
             cutoffs: 0,
@@ -64,9 +210,17 @@ impl AI {
             tt_hits: 0,
             pv_nullsearches: 0,
             pv_failed_nullsearches: 0,
+            aspiration_researches: 0,
+            reduction_researches: 0,
+            futility_prunes: 0,
+            races: 0,
+
+            ordering: MoveOrdering::new(),
+            moves_made: Vec::new(),
+            search_ply: internal_state.ply,
 
-            hasher: Default::default(),
-            hash: 0,
+            hash: hasher.hash(&internal_state),
+            hasher,
         }
     }
 
@@ -74,26 +228,65 @@ impl AI {
         self.hash ^= self.hasher.update(self.state.current_player, mov);
     }
 
+    /// The key of the current position in the transposition table.
+    fn tt_key(&self) -> IncrementalHash {
+        self.hasher.tt_key(&self.state, self.hash)
+    }
+
+    /// Replaces the random keys of the position hashes by ones derived from `seed`. This clears
+    /// the transposition table.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.stop_pondering();
+        self.hasher = IncrementalHasher::with_seed(seed);
+        self.hash = self.hasher.hash(&self.state);
+        self.main_tt.clear();
+    }
+
+    /// The hash of the position the AI is in.
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    /// Computes the hash the AI uses for `state` from scratch. It is the same as the one reached
+    /// by making the moves leading to `state`.
+    pub fn hash_position(&self, state: &GameState) -> u64 {
+        self.hasher.hash(&InternalGameState::from(*state))
+    }
+
+    /// The ply of the position the AI is in, which matches `GameState::ply` of the game it
+    /// follows.
+    pub fn ply(&self) -> usize {
+        self.state.ply as usize
+    }
+
+    /// Makes `mov`. If it is the reply the AI is pondering on, the background search goes on and
+    /// is used by the next `calculate_move`, otherwise it is stopped.
     pub fn make_move(&mut self, mov: Move) {
-        self.internal_make_move(InternalMove::from(mov));
-        self.state.ply += 1;
+        self.internal_make_move(InternalMove::from_move(self.state.geometry, mov));
+        if self.ponder.as_ref().is_some_and(|ponder| ponder.hash != self.hash) {
+            self.stop_pondering();
+        }
     }
 
     fn internal_make_move(&mut self, mov: InternalMove) {
+        self.moves_made.push(mov);
         self.evaluation.make_move(self.state.current_player, mov);
         self.update_hash(mov);
         self.state.make_move(mov);
+        debug_assert_eq!(self.hash, self.hasher.hash(&self.state), "Incremental hash differs after making {:?}", mov);
     }
 
     pub fn unmake_move(&mut self, mov: Move) {
-        self.internal_unmake_move(InternalMove::from(mov));
-        self.state.ply -= 1;
+        self.stop_pondering();
+        self.internal_unmake_move(InternalMove::from_move(self.state.geometry, mov));
     }
 
     fn internal_unmake_move(&mut self, mov: InternalMove) {
+        self.moves_made.pop();
         self.state.unmake_move(mov);
         self.update_hash(mov.inverse());
         self.evaluation.unmake_move(self.state.current_player, mov);
+        debug_assert_eq!(self.hash, self.hasher.hash(&self.state), "Incremental hash differs after unmaking {:?}", mov);
     }
 
     fn should_stop(&mut self, ply: Ply) -> bool {
@@ -101,10 +294,17 @@ impl AI {
             return false;
         }
 
-        if self.stop_condition_triggered {
+        if self.stop_condition_triggered || self.stop_signal.load(Ordering::Relaxed) {
             return true;
         }
 
+        if let StopCondition::Nodes(nodes) = self.stop_condition {
+            if self.visited_nodes >= nodes {
+                self.stop_condition_triggered = true;
+                return true;
+            }
+        }
+
         if self.visited_nodes & 0x7FF == 0 {
             if let StopCondition::Time(dur) = self.stop_condition {
                 let time_taken = ::std::time::Instant::now() - self.start;
@@ -119,38 +319,67 @@ impl AI {
         false
     }
 
+    /// The static evaluation of the current position. Races are scored by the number of moves
+    /// the player to move is ahead. As stones may still go back and block the opponent, even a
+    /// race whose bounds do not overlap is not scored as a win.
+    fn evaluate(&mut self) -> Score {
+        match self.race.race(&self.state) {
+            Some(race) => {
+                self.races += 1;
+                race.evaluate()
+            }
+            None => self.evaluation.evaluate(self.state),
+        }
+    }
+
     fn search_pv(&mut self, ply: Ply, alpha: Score, beta: Score, depth: Depth) -> Score {
+        self.clear_pv(ply);
         if self.should_stop(ply) {
-            return self.evaluation.evaluate(self.state);
+            return self.evaluate();
         }
 
         self.visited_nodes += 1;
 
-        // 1. Check if we lost.
+        // 1. Check if the game is over. Depending on the win rule, the opponent's move may also
+        //    have decided the game in our favour or drawn it.
         if self.state.won(1-self.state.current_player) {
             return -WINNING_SCORE+ply as Score;
         }
 
+        if self.state.won(self.state.current_player) {
+            return WINNING_SCORE-ply as Score;
+        }
+
+        if self.state.drawn() {
+            return 0;
+        }
+
         // 2. Check if we ran out of depth and have to evaluate the position staticly.
         if depth < ONE_PLY {
             self.visited_leaf_nodes += 1;
-            return self.evaluation.evaluate(self.state);
+            return self.evaluate();
         }
 
         // 3. Lookup current position in transposition table. If we encountered this position
-        //    before, previous evaluations are useful to get an early cutoff.
-        if let Some((score, exact)) = self.get_transposition_score(alpha, beta, depth) {
-            self.tt_hits += 1;
+        //    before, previous evaluations are useful to get an early cutoff. The root is always
+        //    searched, so its best move and line are known.
+        if ply > 0 {
+            if let Some((score, exact)) = self.get_transposition_score(ply, alpha, beta, depth) {
+                self.tt_hits += 1;
 
-            // get_transposition_score returns Some(_) if the position in the transposition
-            // table was evaluated to a higher depth. If in that case the score is also exact,
-            // we return with this score.
-            if exact {
-                self.cutoffs += 1;
-                return score;
+                // get_transposition_score returns Some(_) if the position in the transposition
+                // table was evaluated to a higher depth. If in that case the score is also exact,
+                // we return with this score.
+                if exact {
+                    self.cutoffs += 1;
+                    return score;
+                }
             }
         }
 
+        // Without some of its moves, the root must not be stored in the transposition table.
+        let store = ply > 0 || self.excluded_root_moves.is_empty();
+
         // Whether we found any move which increases alpha and did not exceed beta. After a move
         // increased alpha, we search all remaining moves using a null-window first and only do a
         // full-window research it we failed high.
@@ -160,12 +389,15 @@ impl AI {
         // The best response we found.
         let mut best_move = None;
 
-        let moves = MovePicker::new(self.state, self.hash, self.main_tt.clone());
-        // 4. Evaluate remaining moves. We first try the 8 highest rated moves (with respect to the
-        //    move ordering score above). If we did not get a beta cutoff during these 8 moves, we
-        //    try the remaining moves in any order because the move ordering seems bad and we give
-        //    up sorting.
-        for mov in moves {
+        let mut moves = MovePicker::new(self.state, self.tt_key(), self.main_tt.clone(), ply, self.moves_made.last().cloned());
+        // 4. Evaluate remaining moves, starting with the best move from the transposition table,
+        //    followed by killer and counter moves and the ones with the best history.
+        while let Some(mov) = moves.pick(&self.ordering) {
+            if ply == 0 && self.excluded_root_moves.contains(&mov) {
+                continue;
+            }
+
+            let child_depth = depth - ONE_PLY + self.extensions.extension(&self.state, mov);
             self.internal_make_move(mov);
             let score;
 
@@ -173,13 +405,13 @@ impl AI {
             // evaluated using a null window and a shallower depth. If the null window evaluation
             // fails high, we retry using the full window.
             if !raised_alpha {
-                score = -self.search_pv(ply+1, -beta, -alpha, depth-ONE_PLY);
+                score = -self.search_pv(ply+1, -beta, -alpha, child_depth);
             } else {
                 self.pv_nullsearches += 1;
-                let null_score = -self.search_null(ply+1, -alpha, depth-ONE_PLY);
+                let null_score = -self.search_null(ply+1, -alpha, child_depth);
                 if null_score > alpha {
                     self.pv_failed_nullsearches += 1;
-                    score = -self.search_pv(ply+1, -beta, -alpha, depth-ONE_PLY);
+                    score = -self.search_pv(ply+1, -beta, -alpha, child_depth);
                 } else {
                     score = null_score;
                 }
@@ -188,7 +420,10 @@ impl AI {
 
             if score >= beta {
                 self.cutoffs += 1;
-                self.insert_transposition(ScoreType::LowerBound(beta), Some(mov), depth, true);
+                self.ordering.update(self.state.current_player, ply, depth, mov, self.moves_made.last().cloned());
+                if store {
+                    self.insert_transposition(ply, ScoreType::LowerBound(beta), Some(mov), depth, true);
+                }
                 return beta;
             }
 
@@ -196,13 +431,21 @@ impl AI {
                 raised_alpha = true;
                 best_move = Some(mov);
                 alpha = score;
+                self.update_pv(ply, mov);
+                if ply == 0 {
+                    self.root_move = Some(mov);
+                }
             }
         }
 
+        if !store {
+            return alpha;
+        }
+
         if raised_alpha {
-            self.insert_transposition(ScoreType::Exact(alpha), best_move, depth, true);
+            self.insert_transposition(ply, ScoreType::Exact(alpha), best_move, depth, true);
         } else {
-            self.insert_transposition(ScoreType::UpperBound(alpha), best_move, depth, true);
+            self.insert_transposition(ply, ScoreType::UpperBound(alpha), best_move, depth, true);
         }
 
         alpha
@@ -210,27 +453,36 @@ impl AI {
 
     fn search_null(&mut self, ply: Ply, beta: Score, depth: Depth) -> Score {
         if self.should_stop(ply) {
-            return self.evaluation.evaluate(self.state);
+            return self.evaluate();
         }
 
         self.visited_nodes += 1;
 
-        // 1. Check if we lost.
+        // 1. Check if the game is over. Depending on the win rule, the opponent's move may also
+        //    have decided the game in our favour or drawn it.
         if self.state.won(1-self.state.current_player) {
             return -WINNING_SCORE+ply as Score;
         }
 
+        if self.state.won(self.state.current_player) {
+            return WINNING_SCORE-ply as Score;
+        }
+
+        if self.state.drawn() {
+            return 0;
+        }
+
         // 2. Check if we ran out of depth and have to evaluate the position staticly.
         if depth < ONE_PLY {
             self.visited_leaf_nodes += 1;
-            return self.evaluation.evaluate(self.state);
+            return self.evaluate();
         }
 
         let alpha = beta-1;
 
         // 3. Lookup current position in transposition table. If we encountered this position
         //    before, previous evaluations or best moves are useful to get an early beta cutoff.
-        if let Some((score, exact)) = self.get_transposition_score(alpha, beta, depth) {
+        if let Some((score, exact)) = self.get_transposition_score(ply, alpha, beta, depth) {
             self.tt_hits += 1;
 
             // get_transposition_score returns Some(_) if the position in the transposition table
@@ -247,21 +499,42 @@ impl AI {
 
         }
 
-        // We score the moves (for ordering purposes) by how far they advance along the board.
+        // 4. Close to the leaves, give up on positions which are too far below or above the
+        //    window to change sides within the remaining plies (futility pruning).
+        if let Some(margin) = self.pruning.futility_margin(depth).filter(|_| plies_to_win(beta).is_none()) {
+            let score = self.evaluate();
+            if score - margin >= beta {
+                self.futility_prunes += 1;
+                return beta;
+            }
+            if score + margin < alpha {
+                self.futility_prunes += 1;
+                return alpha;
+            }
+        }
 
-        let moves = MovePicker::new(self.state, self.hash, self.main_tt.clone());
-        // 4. Evaluate remaining moves. We first try the 8 highest rated moves (with respect to the
-        //    move ordering score above). If we did not get a beta cutoff during these 8 moves, we
-        //    try the remaining moves in any order because the move ordering seems bad and we give
-        //    up sorting.
-        for mov in moves {
+        let mut moves = MovePicker::new(self.state, self.tt_key(), self.main_tt.clone(), ply, self.moves_made.last().cloned());
+        // 5. Evaluate remaining moves, starting with the best move from the transposition table,
+        //    followed by killer and counter moves and the ones with the best history. Moves late in
+        //    that order are unlikely to cause a cutoff, so they are searched with a reduced depth
+        //    first and only searched again with the full depth if they do (late move reductions).
+        let mut searched = 0;
+        while let Some(mov) = moves.pick(&self.ordering) {
+            let child_depth = depth - ONE_PLY + self.extensions.extension(&self.state, mov);
             self.internal_make_move(mov);
-            let score = -self.search_null(ply+1, -alpha, depth-ONE_PLY);
+            let reduction = self.pruning.late_move_reduction(depth, searched);
+            let mut score = -self.search_null(ply+1, -alpha, child_depth-reduction);
+            if reduction > 0 && score >= beta {
+                self.reduction_researches += 1;
+                score = -self.search_null(ply+1, -alpha, child_depth);
+            }
             self.internal_unmake_move(mov);
+            searched += 1;
 
             if score >= beta {
                 self.cutoffs += 1;
-                self.insert_transposition(ScoreType::LowerBound(beta), Some(mov), depth, false);
+                self.ordering.update(self.state.current_player, ply, depth, mov, self.moves_made.last().cloned());
+                self.insert_transposition(ply, ScoreType::LowerBound(beta), Some(mov), depth, false);
                 return beta;
             }
         }
@@ -269,34 +542,35 @@ impl AI {
         alpha
     }
 
-    fn insert_transposition(&mut self, evaluation: ScoreType, best_move: Option<InternalMove>, depth: Depth, pv: bool) {
+    fn insert_transposition(&mut self, ply: Ply, evaluation: ScoreType, best_move: Option<InternalMove>, depth: Depth, pv: bool) {
         if best_move == None {
             return;
         }
 
         let transposition = Transposition {
-            evaluation,
+            evaluation: evaluation.map(|score| score_to_tt(score, ply)),
             best_move: best_move.unwrap(),
             depth: depth,
-            ply: self.state.ply,
+            ply: self.search_ply,
         };
 
-        let main_tt_entry = { self.main_tt.borrow().get(self.hash) };
+        let key = self.tt_key();
+        let main_tt_entry = self.main_tt.get(key);
         match main_tt_entry {
             None => {
-                self.main_tt.borrow_mut().insert(self.hash, transposition);
+                self.main_tt.insert(key, transposition);
             }
             Some(old) => {
                 if old.should_be_replaced_by(&transposition, pv) {
-                    self.main_tt.borrow_mut().insert(self.hash, transposition);
+                    self.main_tt.insert(key, transposition);
                 }
             }
         }
     }
 
-    fn get_transposition_score(&mut self, alpha: Score, beta: Score, depth: Depth) -> Option<(Score, bool)> {
+    fn get_transposition_score(&mut self, ply: Ply, alpha: Score, beta: Score, depth: Depth) -> Option<(Score, bool)> {
         self.tt_lookups += 1;
-        let tt_entry = self.main_tt.borrow().get(self.hash);
+        let tt_entry = self.main_tt.get(self.tt_key());
 
         if let Some(transposition) = tt_entry {
             // If the depth used to evaluate the position now is higher than the one we used
@@ -305,7 +579,7 @@ impl AI {
                 return None;
             }
 
-            match transposition.evaluation {
+            match transposition.evaluation.map(|score| score_from_tt(score, ply)) {
                 ScoreType::Exact(score) => return Some((score, true)),
                 ScoreType::LowerBound(lower_bound) => {
                     if lower_bound >= beta {
@@ -323,6 +597,249 @@ impl AI {
         None
     }
 
+    /// Creates a copy of the AI for a helper thread, sharing the transposition table and the
+    /// stop signal.
+    fn helper(&self) -> AI {
+        AI {
+            state: self.state,
+            stop_condition: StopCondition::Depth(MAX_DEPTH),
+            deterministic: false,
+            multi_pv: 1,
+            extensions: self.extensions,
+            pruning: self.pruning,
+            threads: 1,
+            stop_condition_triggered: false,
+            stop_signal: self.stop_signal.clone(),
+            start: self.start,
+            main_tt: self.main_tt.clone(),
+            root_move: None,
+            pv_table: Vec::new(),
+            lines: Vec::new(),
+            excluded_root_moves: Vec::new(),
+            ponder: None,
+            progress: None,
+            statistics: Statistics::default(),
+            evaluation: self.evaluation.clone(),
+            race: RaceSolver::new(),
+            visited_nodes: 0,
+            visited_leaf_nodes: 0,
+            cutoffs: 0,
+            tt_lookups: 0,
+            tt_hits: 0,
+            pv_nullsearches: 0,
+            pv_failed_nullsearches: 0,
+            aspiration_researches: 0,
+            reduction_researches: 0,
+            futility_prunes: 0,
+            races: 0,
+
+            ordering: MoveOrdering::new(),
+            moves_made: self.moves_made.clone(),
+            search_ply: self.search_ply,
+
+            hasher: self.hasher,
+            hash: self.hash,
+        }
+    }
+
+    /// Deepens the search of a helper thread until it is told to stop. Half of the helpers start
+    /// one ply deeper than the main search, so the threads do not all search the same depth.
+    /// Returns the number of nodes visited.
+    fn help(mut self, depth_offset: Depth) -> usize {
+        self.search_ply = self.state.ply;
+        for d in 1+depth_offset..MAX_DEPTH {
+            if self.stop_signal.load(Ordering::Relaxed) {
+                break;
+            }
+
+            self.search_root(d*ONE_PLY, None);
+        }
+
+        self.visited_nodes
+    }
+
+    /// Starts searching the position after the reply of the opponent the AI expects in the
+    /// background. The reply is the second move of the principal variation if the AI made the
+    /// first one since its last search, otherwise the best move of the current position in the
+    /// transposition table. The search goes on until `stop_pondering` is called or a move other
+    /// than the reply is made. Returns the expected reply, `None` if there is none or the AI is
+    /// deterministic.
+    pub fn start_pondering(&mut self) -> Option<Move> {
+        self.stop_pondering();
+        if self.deterministic {
+            return None;
+        }
+
+        let geometry = self.state.geometry;
+        let pv = self.lines.first().map(|line| &line.pv[..]).unwrap_or_default();
+        let reply = match (self.moves_made.last(), pv) {
+            (Some(&last), &[first, reply, ..]) if self.state.ply == self.search_ply + 1 && last == InternalMove::from_move(geometry, first) => {
+                InternalMove::from_move(geometry, reply)
+            }
+            _ => self.main_tt.get(self.tt_key())?.best_move,
+        };
+        if !self.state.is_legal(reply) {
+            return None;
+        }
+
+        let stop_signal = Arc::new(AtomicBool::new(false));
+        let mut helper = self.helper();
+        helper.stop_signal = stop_signal.clone();
+        helper.internal_make_move(reply);
+        let hash = helper.hash;
+        let handle = thread::spawn(move || helper.help(0));
+
+        self.ponder = Some(Ponder {
+            hash,
+            reply,
+            stop_signal,
+            handle,
+        });
+        Some(reply.to_move(self.state.geometry))
+    }
+
+    /// The reply the AI is pondering on, if it is.
+    pub fn pondering(&self) -> Option<Move> {
+        self.ponder.as_ref().map(|ponder| ponder.reply.to_move(self.state.geometry))
+    }
+
+    /// Stops the background search, if any. Returns the number of nodes it visited.
+    pub fn stop_pondering(&mut self) -> usize {
+        match self.ponder.take() {
+            Some(ponder) => {
+                ponder.stop_signal.store(true, Ordering::Relaxed);
+                ponder.handle.join().unwrap()
+            }
+            None => 0,
+        }
+    }
+
+    /// Searches the root position to `depth`. With a `guess` of the score, the search starts with
+    /// a narrow window around it, which is widened whenever the score falls outside of it.
+    fn search_root(&mut self, depth: Depth, guess: Option<Score>) -> Score {
+        let mut delta = ASPIRATION_WINDOW;
+        let (mut alpha, mut beta) = match guess {
+            Some(guess) if plies_to_win(guess).is_none() => (guess - delta, guess + delta),
+            _ => (-WINNING_SCORE, WINNING_SCORE),
+        };
+
+        loop {
+            self.root_move = None;
+            let score = self.search_pv(0, alpha, beta, depth);
+            if self.stop_condition_triggered {
+                return score;
+            }
+
+            // Wins and losses are far away from any window, so the search gives up on it.
+            delta = if plies_to_win(score).is_some() { WINNING_SCORE } else { delta * 4 };
+            if score <= alpha && alpha > -WINNING_SCORE {
+                alpha = ::std::cmp::max(score - delta, -WINNING_SCORE);
+            } else if score >= beta && beta < WINNING_SCORE {
+                beta = ::std::cmp::min(score + delta, WINNING_SCORE);
+            } else {
+                return score;
+            }
+            self.aspiration_researches += 1;
+        }
+    }
+
+    /// Sets the function called with a report after every finished iteration of a search, or
+    /// removes it. To receive the reports on another thread, it can send them through a channel.
+    pub fn set_progress(&mut self, progress: Option<ProgressCallback>) {
+        self.progress = progress;
+    }
+
+    /// The statistics of the last search.
+    pub fn statistics(&self) -> &Statistics {
+        &self.statistics
+    }
+
+    /// Starts an empty principal variation at `ply`.
+    fn clear_pv(&mut self, ply: Ply) {
+        let ply = ply as usize;
+        if self.pv_table.len() <= ply {
+            self.pv_table.resize(ply + 1, Vec::new());
+        }
+        self.pv_table[ply].clear();
+    }
+
+    /// Sets the principal variation at `ply` to `mov` followed by the one of the next ply.
+    fn update_pv(&mut self, ply: Ply, mov: InternalMove) {
+        let ply = ply as usize;
+        let (lines, next) = self.pv_table.split_at_mut(ply + 1);
+        let line = &mut lines[ply];
+        line.clear();
+        line.push(mov);
+        if let Some(next) = next.first() {
+            line.extend_from_slice(next);
+        }
+    }
+
+    /// Extends `pv` by following the best moves stored in the transposition table to at most
+    /// `max_len` moves. The line of the PV table ends early where the search used an exact score
+    /// from the table instead of searching on. The walk stops before it enters a position twice
+    /// and at the first best move which is not legal in its position.
+    fn extend_pv(&mut self, pv: &mut Vec<InternalMove>, max_len: usize) {
+        let mut visited = vec![self.hash];
+        for &mov in pv.iter() {
+            self.internal_make_move(mov);
+            visited.push(self.hash);
+        }
+
+        while pv.len() < max_len {
+            let mov = match self.main_tt.get(self.tt_key()) {
+                Some(transposition) if self.state.is_legal(transposition.best_move) => transposition.best_move,
+                _ => break,
+            };
+
+            self.internal_make_move(mov);
+            if visited.contains(&self.hash) {
+                self.internal_unmake_move(mov);
+                break;
+            }
+            visited.push(self.hash);
+            pv.push(mov);
+        }
+
+        for &mov in pv.iter().rev() {
+            self.internal_unmake_move(mov);
+        }
+    }
+
+    /// The principal variation of the last finished iteration, starting with the best move of
+    /// the position the search started from. It is not updated by moves made after the search.
+    pub fn principal_variation(&self) -> Vec<Move> {
+        self.lines.first().map(|line| line.pv.clone()).unwrap_or_default()
+    }
+
+    /// The best lines of the last finished iteration, ranked from the best one. There are
+    /// `multi_pv` of them, unless there are fewer legal moves.
+    pub fn lines(&self) -> &[Line] {
+        &self.lines
+    }
+
+    fn report_progress(&mut self, depth: usize, score: Score) {
+        if self.progress.is_none() {
+            return;
+        }
+
+        let elapsed = self.start.elapsed();
+        let progress = Progress {
+            depth,
+            score,
+            win_in: plies_to_win(score),
+            nodes: self.visited_nodes,
+            nodes_per_second: statistics::ratio(self.visited_nodes as f64, statistics::seconds(elapsed)),
+            elapsed,
+            tt_hit_rate: statistics::ratio(self.tt_hits as f64, self.tt_lookups as f64),
+            pv: self.principal_variation(),
+        };
+
+        if let Some(ref mut callback) = self.progress {
+            callback(&progress);
+        }
+    }
+
     pub fn calculate_move(&mut self) -> Move {
         // reset statistics
         self.visited_nodes = 0;
@@ -332,12 +849,34 @@ impl AI {
         self.tt_hits = 0;
         self.pv_nullsearches = 0;
         self.pv_failed_nullsearches = 0;
+        self.aspiration_researches = 0;
+        self.reduction_researches = 0;
+        self.futility_prunes = 0;
+        self.races = 0;
 
         self.stop_condition_triggered = false;
         self.start = ::std::time::Instant::now();
-        let alpha = -WINNING_SCORE;
-        let beta = WINNING_SCORE;
+        self.search_ply = self.state.ply;
+        self.ordering.new_search();
+        if self.deterministic {
+            self.main_tt.clear();
+            self.ordering.clear();
+            self.race.clear();
+        }
+
+        self.stop_signal = Arc::new(AtomicBool::new(false));
+        let threads = if self.deterministic { 1 } else { ::std::cmp::max(1, self.threads) };
+        let helpers: Vec<_> = (1..threads).map(|i| {
+            let helper = self.helper();
+            thread::spawn(move || helper.help(i as Depth % 2))
+        }).collect();
+
         let mut score = 0;
+        // The scores of all finished iterations.
+        let mut scores = Vec::new();
+        let mut depth = 0;
+        let mut best_move = None;
+        self.lines.clear();
         for d in 1 as Depth.. {
             match self.stop_condition {
                 StopCondition::Depth(stop_depth) => {
@@ -351,44 +890,365 @@ impl AI {
                     let remaining = dur.checked_sub(time_taken);
                     if remaining == None || remaining.unwrap() < ::std::time::Duration::new(0, 50_000_000) {
                         self.stop_condition_triggered = true;
-                        if self.print_statistics {
-                            println!("Stopping search after depth {}", d-1);
-                        }
                         break;
                 }
             }
+                StopCondition::Nodes(nodes) => {
+                    if self.visited_nodes >= nodes {
+                        self.stop_condition_triggered = true;
+                        break;
+                    }
+                }
             }
 
-            score = self.search_pv(0, alpha, beta, d*ONE_PLY);
+            // Search for the best move first, then for the best of the remaining ones, until
+            // `multi_pv` lines are found.
+            let mut lines = Vec::new();
+            for k in 0..::std::cmp::max(1, self.multi_pv) {
+                // The evaluation favours the side which made the last move, so scores alternate
+                // between odd and even depths and the score of the iteration before the last one
+                // is the better guess. Only the best line is searched with an aspiration window,
+                // as the others are not expected to score close to it.
+                let guess = if k == 0 && scores.len() >= 2 { Some(scores[scores.len() - 2]) } else { None };
+                let line_score = self.search_root(d*ONE_PLY, guess);
+                let root_move = match self.root_move {
+                    Some(root_move) => root_move,
+                    None => break,
+                };
+
+                if k == 0 {
+                    // An iteration which was stopped did not look at all moves with the full
+                    // depth, so its best move is only better than nothing.
+                    if !self.stop_condition_triggered || best_move.is_none() {
+                        best_move = Some(root_move);
+                    }
+                    if !self.stop_condition_triggered || depth == 0 {
+                        score = line_score;
+                    }
+                }
+                if self.stop_condition_triggered {
+                    break;
+                }
+
+                let mut pv = self.pv_table[0].clone();
+                self.extend_pv(&mut pv, d as usize);
+                lines.push(Line {
+                    score: line_score,
+                    win_in: plies_to_win(line_score),
+                    pv: pv.iter().map(|mov| mov.to_move(self.state.geometry)).collect(),
+                });
+                self.excluded_root_moves.push(root_move);
+            }
+            self.excluded_root_moves.clear();
+
+            if !self.stop_condition_triggered {
+                depth = d as usize;
+                self.lines = lines;
+                scores.push(score);
+                self.report_progress(depth, score);
+            }
         }
 
-        let mov;
-        if let Some(transposition) = self.main_tt.borrow().get(self.hash) {
-            mov = transposition.best_move;
-        } else {
-            panic!("No PV entry in transposition table");
-        }
-
-        let end = ::std::time::Instant::now();
-        let elapsed = end-self.start;
-        let secs = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1_000_000_000.0;
-        let interior_nodes = self.visited_nodes - self.visited_leaf_nodes;
-        if self.print_statistics {
-            println!("  nodes | total   {} ({:.3} knodes/s)", self.visited_nodes, self.visited_nodes as f64 / secs / 1000.0);
-            println!("        | leaf    {} ({:.2}%)", self.visited_leaf_nodes, 100.0 * self.visited_leaf_nodes as f64 / self.visited_nodes as f64);
-            println!("        | inner   {} ({:.2}%)", interior_nodes, 100.0 * interior_nodes as f64 / self.visited_nodes as f64);
-            println!("cutoffs | total   {} ({:.2}%)", self.cutoffs, 100.0 * self.cutoffs as f64 / interior_nodes as f64);
-            println!("     TT | lookups {}", self.tt_lookups);
-            println!("        | hits    {} ({:.2}%)", self.tt_hits, 100.0 * self.tt_hits as f64 / self.tt_lookups as f64);
-            println!("     PV | 0-wind. {}", self.pv_nullsearches);
-            println!("        | failed  {} ({:.2}%)", self.pv_failed_nullsearches, 100.0 * self.pv_failed_nullsearches  as f64 / self.pv_nullsearches as f64);
-            println!("");
-            println!("Time:  {}:{}", elapsed.as_secs() / 60, (elapsed.as_secs() % 60) as f64 + elapsed.subsec_nanos() as f64 / 1_000_000_000.0);
-            println!("Score: {}", score);
-            println!("");
-        }
-
-        mov.to_move()
+        self.stop_signal.store(true, Ordering::Relaxed);
+        let mut helper_nodes: usize = helpers.into_iter().map(|helper| helper.join().unwrap()).sum();
+        // After a ponder hit, the background search went on alongside this one.
+        helper_nodes += self.stop_pondering();
+
+        let mov = best_move
+            .or_else(|| self.main_tt.get(self.tt_key()).map(|transposition| transposition.best_move).filter(|&mov| self.state.is_legal(mov)))
+            .expect("No move found at the root");
+        if self.lines.first().map(|line| line.pv[0]) != Some(mov.to_move(self.state.geometry)) {
+            self.lines = vec![Line {
+                score,
+                win_in: plies_to_win(score),
+                pv: vec![mov.to_move(self.state.geometry)],
+            }];
+        }
+
+        self.statistics = Statistics {
+            depth,
+            score,
+            nodes: self.visited_nodes,
+            leaf_nodes: self.visited_leaf_nodes,
+            helper_nodes,
+            cutoffs: self.cutoffs,
+            tt_lookups: self.tt_lookups,
+            tt_hits: self.tt_hits,
+            pv_nullsearches: self.pv_nullsearches,
+            pv_failed_nullsearches: self.pv_failed_nullsearches,
+            aspiration_researches: self.aspiration_researches,
+            reduction_researches: self.reduction_researches,
+            futility_prunes: self.futility_prunes,
+            races: self.races,
+            win_in: plies_to_win(score),
+            elapsed: self.start.elapsed(),
+        };
+
+        mov.to_move(self.state.geometry)
     }
 }
 
+
+impl Drop for AI {
+    fn drop(&mut self) {
+        self.stop_pondering();
+    }
+}
+
+mod tests {
+    #[test]
+    fn test_deterministic_search() {
+        use GameState;
+        use ai::{AI, StopCondition};
+
+        let mut ais: Vec<_> = (0..2).map(|_| {
+            let mut ai = AI::new(GameState::default());
+            ai.set_seed(7);
+            ai.deterministic = true;
+            ai.stop_condition = StopCondition::Nodes(5000);
+            ai
+        }).collect();
+
+        for _ in 0..3 {
+            let mov = ais[0].calculate_move();
+            assert_eq!(ais[0].calculate_move(), mov);
+            assert_eq!(ais[1].calculate_move(), mov);
+            for ai in &mut ais {
+                ai.make_move(mov);
+            }
+            assert_eq!(ais[0].hash(), ais[1].hash());
+        }
+    }
+
+    #[test]
+    fn test_threads() {
+        use {Game, GameState};
+        use ai::{AI, StopCondition};
+
+        let mut game = Game::default();
+        let mut ai = AI::new(GameState::default());
+        ai.threads = 4;
+        ai.stop_condition = StopCondition::Depth(3);
+        for _ in 0..4 {
+            let mov = ai.calculate_move();
+            assert!(game.state().possible_moves().contains(&mov));
+            game.move_piece(mov);
+            ai.make_move(mov);
+        }
+    }
+
+    #[test]
+    fn test_geometries() {
+        use {Game, Geometry, GameState};
+        use ai::{AI, StopCondition};
+
+        for &geometry in &[Geometry::SmallStar { size: 3 }, Geometry::Square] {
+            let mut game = Game::from_state(GameState::with_geometry(geometry, 2));
+            let mut ai = AI::new(*game.state());
+            ai.stop_condition = StopCondition::Depth(2);
+            for _ in 0..6 {
+                let mov = ai.calculate_move();
+                assert_eq!(game.try_move(mov), Ok(()));
+                ai.make_move(mov);
+            }
+            assert_eq!(ai.hash(), ai.hash_position(game.state()));
+        }
+    }
+
+    #[test]
+    fn test_pondering() {
+        use {Game, GameState};
+        use ai::{AI, StopCondition};
+
+        let mut game = Game::default();
+        let mut ai = AI::new(GameState::default());
+        ai.stop_condition = StopCondition::Depth(3);
+        let mov = ai.calculate_move();
+        let pv = ai.principal_variation();
+        game.move_piece(mov);
+        ai.make_move(mov);
+
+        // A ponder hit keeps the search running until the next move is calculated.
+        let reply = ai.start_pondering().unwrap();
+        assert_eq!(reply, pv[1]);
+        assert!(game.state().possible_moves().contains(&reply));
+        assert_eq!(ai.pondering(), Some(reply));
+        game.move_piece(reply);
+        ai.make_move(reply);
+        assert_eq!(ai.pondering(), Some(reply));
+        let mov = ai.calculate_move();
+        assert!(game.state().possible_moves().contains(&mov));
+        assert_eq!(ai.pondering(), None);
+        game.move_piece(mov);
+        ai.make_move(mov);
+
+        // A ponder miss stops it.
+        let reply = ai.start_pondering().unwrap();
+        let miss = *game.state().possible_moves().iter().find(|&&mov| mov != reply).unwrap();
+        ai.make_move(miss);
+        assert_eq!(ai.pondering(), None);
+
+        ai.deterministic = true;
+        assert_eq!(ai.start_pondering(), None);
+    }
+
+    #[test]
+    fn test_progress() {
+        use std::sync::mpsc;
+        use GameState;
+        use ai::{AI, StopCondition};
+
+        let (sender, receiver) = mpsc::channel();
+        let mut ai = AI::new(GameState::default());
+        ai.stop_condition = StopCondition::Depth(3);
+        ai.set_progress(Some(Box::new(move |progress| sender.send(progress.clone()).unwrap())));
+        let mov = ai.calculate_move();
+
+        let reports: Vec<_> = receiver.try_iter().collect();
+        assert_eq!(reports.iter().map(|progress| progress.depth).collect::<Vec<_>>(), vec![1, 2, 3]);
+        assert!(reports.iter().all(|progress| !progress.pv.is_empty() && progress.pv.len() <= progress.depth));
+        assert_eq!(reports[2].pv[0], mov);
+        assert_eq!(ai.statistics().depth, 3);
+        assert_eq!(ai.statistics().score, reports[2].score);
+        assert!(ai.statistics().nodes >= reports[2].nodes);
+    }
+
+    #[test]
+    fn test_principal_variation() {
+        use {Game, GameState};
+        use ai::{AI, StopCondition};
+
+        let mut ai = AI::new(GameState::default());
+        ai.stop_condition = StopCondition::Depth(4);
+        let mov = ai.calculate_move();
+        let pv = ai.principal_variation();
+        assert_eq!(pv.len(), 4);
+        assert_eq!(pv[0], mov);
+
+        let mut game = Game::default();
+        for &mov in &pv {
+            assert!(game.state().possible_moves().contains(&mov));
+            game.move_piece(mov);
+        }
+
+        // The line does not change with the moves made after the search.
+        ai.make_move(mov);
+        assert_eq!(ai.principal_variation(), pv);
+    }
+
+    #[test]
+    fn test_multi_pv() {
+        use {Game, GameState};
+        use ai::{AI, StopCondition};
+
+        let mut ai = AI::new(GameState::default());
+        ai.stop_condition = StopCondition::Depth(3);
+        ai.multi_pv = 4;
+        let mov = ai.calculate_move();
+        let lines = ai.lines().to_vec();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0].pv, ai.principal_variation());
+        assert_eq!(lines[0].pv[0], mov);
+
+        for (i, line) in lines.iter().enumerate() {
+            assert!(lines[..i].iter().all(|other| other.pv[0] != line.pv[0]));
+            assert!(lines[..i].iter().all(|other| other.score >= line.score));
+
+            let mut game = Game::default();
+            for &mov in &line.pv {
+                assert!(game.state().possible_moves().contains(&mov));
+                game.move_piece(mov);
+            }
+        }
+
+        // The best move does not depend on the number of lines.
+        let mut single = AI::new(GameState::default());
+        single.stop_condition = StopCondition::Depth(3);
+        assert_eq!(single.calculate_move(), mov);
+    }
+
+    #[test]
+    fn test_win_distance() {
+        use {GameState, Move};
+        use ai::{AI, StopCondition};
+        use ai::extensions::Extensions;
+        use builder::PositionBuilder;
+
+        // The first player fills its target with its next move, the second one needs two moves.
+        let start = GameState::default();
+        let mut builder = PositionBuilder::from_state(&start);
+        builder.clear();
+        for &(x, y) in &start.targets(0) {
+            if (x, y) != (4, 12) {
+                builder.place(x, y, 0);
+            }
+        }
+        builder.place(3, 12, 0);
+        for &(x, y) in &start.targets(1) {
+            if (x, y) != (4, 4) && (x, y) != (8, 4) {
+                builder.place(x, y, 1);
+            }
+        }
+        builder.place(6, 8, 1).place(6, 9, 1);
+
+        let mut ai = AI::new(builder.build().unwrap());
+        ai.stop_condition = StopCondition::Depth(3);
+        assert_eq!(ai.calculate_move(), Move { from: (3, 12), to: (4, 12) });
+        assert_eq!(ai.statistics().win_in, Some(1));
+
+        // Scores read from the transposition table keep the distance to the root, so the
+        // distance to the win shrinks along the line towards it.
+        let mut builder = PositionBuilder::from_state(&start);
+        builder.clear();
+        for &(x, y) in &start.targets(0) {
+            if x % 2 == 1 || y != 12 {
+                builder.place(x, y, 0);
+            }
+        }
+        for &(x, y) in &[(3, 12), (6, 11), (9, 12)] {
+            builder.place(x, y, 0);
+        }
+        for &(x, y) in &start.targets(1) {
+            if y != 4 {
+                builder.place(x, y, 1);
+            }
+        }
+        for x in 3..7 {
+            builder.place(x, 7, 1);
+        }
+
+        let mut ai = AI::new(builder.build().unwrap());
+        ai.extensions = Extensions::none();
+        ai.stop_condition = StopCondition::Depth(5);
+        ai.calculate_move();
+        assert_eq!(ai.statistics().win_in, Some(5));
+        for &mov in &ai.principal_variation()[..2] {
+            ai.make_move(mov);
+        }
+        ai.stop_condition = StopCondition::Depth(3);
+        ai.calculate_move();
+        assert_eq!(ai.statistics().win_in, Some(3));
+    }
+
+    #[test]
+    fn test_race() {
+        use GameState;
+        use ai::{AI, StopCondition};
+
+        // A race in which player 0 needs one more move and player 1 two. The search finds the
+        // win, while the race only guides the evaluation.
+        let mut state = GameState::from_notation("b/bb/bbb/bbbb/4bb1bb4/12/5b5/10/9/10/11/6a5/4aa1aa4/aaaa/aaa/aa/a a 0").unwrap();
+        let mut ai = AI::new(state);
+        ai.stop_condition = StopCondition::Depth(3);
+        let mov = ai.calculate_move();
+        assert_eq!(ai.statistics().win_in, Some(1));
+        state.move_piece(mov);
+        assert_eq!(state.winner(), Some(0));
+
+        let mut ai = AI::new(GameState::from_notation("b/bb/bbb/bbbb/4bb1bb4/12/5b5/10/9/10/11/6a5/4aa1aa4/aaaa/aaa/aa/a b 0").unwrap());
+        ai.stop_condition = StopCondition::Depth(3);
+        ai.calculate_move();
+        assert_eq!(ai.statistics().win_in, Some(-2));
+        assert!(ai.statistics().races > 0);
+    }
+}
@@ -1,16 +1,116 @@
-use std::cell::RefCell;
-use std::rc::Rc;
+use std::sync::Arc;
 
-use ai::Score;
+use ai::{Depth, ONE_PLY, Score};
 use ai::bitboard::{Bitboard, BitIndex};
 use ai::incremental_hasher::IncrementalHash;
-use ai::internal_game_state::{InternalGameState, InternalMove};
+use ai::internal_game_state::{InternalGameState, InternalMove, Ply};
 use ai::tt::TranspositionTable;
 
+/// The number of moves indexed by their origin and destination.
+const BUTTERFLY_SIZE: usize = 256 * 256;
+
+/// The ordering score of history entries is kept below this, so killer and counter moves are
+/// always tried first.
+const MAX_HISTORY: Score = 1 << 20;
+
+const KILLER_BONUS: Score = 4 * MAX_HISTORY;
+const COUNTER_MOVE_BONUS: Score = 2 * MAX_HISTORY;
+
+/// The weight of the distance a move advances compared to its history score.
+const ADVANCE_WEIGHT: Score = 16;
+
+fn butterfly_index(player: u8, mov: InternalMove) -> usize {
+    player as usize * BUTTERFLY_SIZE + ((mov.from as usize) << 8 | mov.to as usize)
+}
+
+/// What the search learned about good moves from its beta cutoffs.
+///
+/// Killer moves are the last two moves which caused a cutoff at a ply. They are likely to do
+/// so in other positions at the same ply, too. The history counts how often and how deep every
+/// move caused cutoffs, indexed by player, origin and destination. Counter moves are the moves
+/// which last refuted every move of the opponent.
+pub struct MoveOrdering {
+    killers: Vec<[Option<InternalMove>; 2]>,
+    history: Vec<Score>,
+    counter_moves: Vec<Option<InternalMove>>,
+}
+
+impl MoveOrdering {
+    pub fn new() -> MoveOrdering {
+        MoveOrdering {
+            killers: Vec::new(),
+            history: vec![0; 2 * BUTTERFLY_SIZE],
+            counter_moves: vec![None; 2 * BUTTERFLY_SIZE],
+        }
+    }
+
+    /// Forgets everything learned.
+    pub fn clear(&mut self) {
+        *self = MoveOrdering::new();
+    }
+
+    /// Prepares for a new search: killer moves only apply to the plies of the last search, while
+    /// the history is kept with half of its weight.
+    pub fn new_search(&mut self) {
+        self.killers.clear();
+        for score in &mut self.history {
+            *score /= 2;
+        }
+    }
+
+    fn killers(&self, ply: Ply) -> [Option<InternalMove>; 2] {
+        self.killers.get(ply as usize).cloned().unwrap_or([None; 2])
+    }
+
+    /// Records that `mov` of `player` caused a beta cutoff at `ply` with `depth` remaining,
+    /// after the opponent played `previous`.
+    pub fn update(&mut self, player: u8, ply: Ply, depth: Depth, mov: InternalMove, previous: Option<InternalMove>) {
+        let ply = ply as usize;
+        if self.killers.len() <= ply {
+            self.killers.resize(ply + 1, [None; 2]);
+        }
+        let killers = &mut self.killers[ply];
+        if killers[0] != Some(mov) {
+            killers[1] = killers[0];
+            killers[0] = Some(mov);
+        }
+
+        let plies = (depth / ONE_PLY) as Score;
+        let index = butterfly_index(player, mov);
+        self.history[index] += plies * plies;
+        if self.history[index] >= MAX_HISTORY {
+            for score in &mut self.history {
+                *score /= 2;
+            }
+        }
+
+        if let Some(previous) = previous {
+            self.counter_moves[butterfly_index(1 - player, previous)] = Some(mov);
+        }
+    }
+
+    /// The score by which `mov` of `player` is ordered among the moves at `ply`.
+    fn score(&self, player: u8, ply: Ply, mov: InternalMove, counter_move: Option<InternalMove>) -> Score {
+        let mut score = self.history[butterfly_index(player, mov)] + ADVANCE_WEIGHT * move_score(player, mov);
+        if self.killers(ply).contains(&Some(mov)) {
+            score += KILLER_BONUS;
+        }
+        if counter_move == Some(mov) {
+            score += COUNTER_MOVE_BONUS;
+        }
+        score
+    }
+}
+
 pub struct MovePicker {
     state: InternalGameState,
     hash: IncrementalHash,
-    main_tt: Rc<RefCell<TranspositionTable>>,
+    main_tt: Arc<TranspositionTable>,
+    ply: Ply,
+    /// The move of the opponent which led to the position.
+    previous: Option<InternalMove>,
+    /// The move from the transposition table, if it was picked already.
+    tt_move: Option<InternalMove>,
 
     stage: MovePickerStage,
 }
@@ -22,23 +122,24 @@ enum MovePickerStage {
 }
 
 impl MovePicker {
-    pub fn new(state: InternalGameState, hash: IncrementalHash, main_tt: Rc<RefCell<TranspositionTable>>) -> Self {
+    pub fn new(state: InternalGameState, hash: IncrementalHash, main_tt: Arc<TranspositionTable>, ply: Ply, previous: Option<InternalMove>) -> Self {
         MovePicker {
             state,
             hash,
             main_tt,
+            ply,
+            previous,
+            tt_move: None,
             stage: MovePickerStage::TTMove,
         }
     }
-}
 
-impl Iterator for MovePicker {
-    type Item = InternalMove;
-
-    fn next(&mut self) -> Option<Self::Item> {
+    /// Returns the next move to search: the move from the transposition table first, then all
+    /// moves ordered by `ordering`.
+    pub fn pick(&mut self, ordering: &MoveOrdering) -> Option<InternalMove> {
         match self.stage {
             MovePickerStage::TTMove => {
-                let tt_entry = self.main_tt.borrow().get(self.hash);
+                let tt_entry = self.main_tt.get(self.hash);
                 if let Some(transposition) = tt_entry {
                     // Since we can get an illegal move from the transposition table (because it
                     // belongs to another position which hashed to the same index), we have to
@@ -55,12 +156,13 @@ impl Iterator for MovePicker {
                             reachable,
                         };
                         if reachable.get_bit(mov.to) {
+                            self.tt_move = Some(mov);
                             return Some(mov);
                         }
                     }
                 }
                 self.stage = MovePickerStage::Generate { already_generated: None, reachable: Bitboard::default() };
-                self.next()
+                self.pick(ordering)
             }
 
             MovePickerStage::Generate { already_generated, reachable } => {
@@ -76,34 +178,22 @@ impl Iterator for MovePicker {
                     moves.extend(reachable.ones().map(|to| InternalMove { from, to }));
                 }
 
-                self.stage = MovePickerStage::All { index: 0, moves };
-                self.next()
-            }
-
-            MovePickerStage::All { ref mut index, ref mut moves } => {
-                let num_moves = moves.len();
-                if *index == num_moves {
-                    return None;
+                if let Some(tt_move) = self.tt_move {
+                    moves.retain(|&mov| mov != tt_move);
                 }
 
-                if *index < 8 {
-                    let mut max_i = *index;
-                    let mut max_move_score = move_score(self.state.current_player, moves[max_i]);
-
-                    for j in *index+1..num_moves {
-                        let move_score = move_score(self.state.current_player, moves[j]);
-                        if move_score > max_move_score {
-                            max_i = j;
-                            max_move_score = move_score;
-                        }
-                    }
+                let player = self.state.current_player;
+                let counter_move = self.previous.and_then(|previous| ordering.counter_moves[butterfly_index(1 - player, previous)]);
+                moves.sort_by_cached_key(|&mov| -ordering.score(player, self.ply, mov, counter_move));
 
-                    moves.swap(*index, max_i);
-                }
+                self.stage = MovePickerStage::All { index: 0, moves };
+                self.pick(ordering)
+            }
 
-                let mov = moves[*index];
+            MovePickerStage::All { ref mut index, ref moves } => {
+                let mov = moves.get(*index).cloned();
                 *index += 1;
-                Some(mov)
+                mov
             }
         }
     }
@@ -116,3 +206,44 @@ fn move_score(player: u8, mov: InternalMove) -> Score {
         mov.from as isize - mov.to as isize
     }
 }
+
+mod tests {
+    #[test]
+    fn test_move_ordering() {
+        use std::sync::Arc;
+        use {GameState, Geometry, Move};
+        use ai::ONE_PLY;
+        use ai::internal_game_state::{InternalGameState, InternalMove};
+        use ai::move_picker::{MoveOrdering, MovePicker};
+        use ai::tt::TranspositionTable;
+
+        let state = InternalGameState::from(GameState::default());
+        let tt = Arc::new(TranspositionTable::new(8));
+        let first = |ordering: &MoveOrdering, ply, previous| MovePicker::new(state, 0, tt.clone(), ply, previous).pick(ordering);
+
+        // The move advancing the furthest comes first without any cutoffs.
+        let mut ordering = MoveOrdering::new();
+        let advance = first(&ordering, 0, None).unwrap();
+
+        // The move ordered last, which caused a cutoff, is tried first at the same ply.
+        let mut picker = MovePicker::new(state, 0, tt.clone(), 0, None);
+        let mut killer = advance;
+        while let Some(mov) = picker.pick(&ordering) {
+            killer = mov;
+        }
+        assert!(killer != advance);
+        ordering.update(0, 2, 3 * ONE_PLY, killer, None);
+        assert_eq!(first(&ordering, 2, None), Some(killer));
+        assert_eq!(first(&ordering, 4, None), Some(advance));
+
+        // So is the refutation of the opponent's last move.
+        let previous = InternalMove::from_move(Geometry::Star, Move { from: (6, 13), to: (6, 12) });
+        ordering.update(0, 6, ONE_PLY, killer, Some(previous));
+        assert_eq!(first(&ordering, 4, Some(previous)), Some(killer));
+
+        ordering.new_search();
+        assert_eq!(first(&ordering, 2, None), Some(advance));
+        ordering.clear();
+        assert_eq!(first(&ordering, 4, Some(previous)), Some(advance));
+    }
+}
@@ -0,0 +1,106 @@
+//! Late move reductions and futility pruning in the null-window search.
+//!
+//! Halma positions have well over a hundred moves, most of them pointless, so the null-window
+//! search reduces the depth of the moves late in the move order and gives up on nodes close to
+//! the leaves whose static evaluation is far from the window. Both can be switched off with the
+//! `pruning off` command of the `ai` binary. With the default settings, the engine won 39 games,
+//! lost 20 and drew 1 against itself without pruning in
+//!
+//! ```text
+//! match --rounds 30 --time-control 5+0.1 pruned target/release/ai full target/release/ai --send "pruning off"
+//! ```
+//!
+//! The individual margins were not tuned by matches.
+
+use ai::{Depth, Score, ONE_PLY};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Pruning {
+    /// Nodes with less depth left than this are pruned if their static evaluation is too far
+    /// from the window. 0 disables futility pruning.
+    pub futility_depth: Depth,
+    /// How far the evaluation can change per remaining ply, as far as futility pruning is
+    /// concerned.
+    pub futility_margin: Score,
+    /// The number of moves of a node searched without reductions.
+    pub full_depth_moves: usize,
+    /// Nodes with less depth left than this are searched without reductions. `Depth::MAX`
+    /// disables late move reductions.
+    pub reduction_depth: Depth,
+}
+
+impl Default for Pruning {
+    fn default() -> Pruning {
+        Pruning {
+            futility_depth: 3 * ONE_PLY,
+            futility_margin: 20_000,
+            full_depth_moves: 3,
+            reduction_depth: 3 * ONE_PLY,
+        }
+    }
+}
+
+impl Pruning {
+    /// No reductions or pruning at all, so every move is searched to the full depth.
+    pub fn none() -> Pruning {
+        Pruning {
+            futility_depth: 0,
+            futility_margin: 0,
+            full_depth_moves: 0,
+            reduction_depth: Depth::MAX,
+        }
+    }
+
+    /// The margin by which the static evaluation of a node with `depth` left has to miss the
+    /// window to be pruned, or `None` if the node is searched.
+    pub fn futility_margin(&self, depth: Depth) -> Option<Score> {
+        if depth < self.futility_depth {
+            Some(self.futility_margin * (depth / ONE_PLY) as Score)
+        } else {
+            None
+        }
+    }
+
+    /// The depth by which the move searched after `searched` others at a node with `depth` left
+    /// is reduced. It grows with the logarithms of both.
+    pub fn late_move_reduction(&self, depth: Depth, searched: usize) -> Depth {
+        if depth < self.reduction_depth || searched < self.full_depth_moves {
+            return 0;
+        }
+
+        let plies = f64::from(depth) / f64::from(ONE_PLY);
+        let reduction = plies.ln() * (searched as f64).ln() / 2.0;
+        ::std::cmp::min((reduction * f64::from(ONE_PLY)) as Depth, depth - 2 * ONE_PLY)
+    }
+}
+
+mod tests {
+    #[test]
+    fn test_late_move_reduction() {
+        use ai::ONE_PLY;
+        use ai::pruning::Pruning;
+
+        let pruning = Pruning::default();
+        assert_eq!(pruning.late_move_reduction(2 * ONE_PLY, 100), 0);
+        assert_eq!(pruning.late_move_reduction(8 * ONE_PLY, 2), 0);
+        assert!(pruning.late_move_reduction(8 * ONE_PLY, 3) > 0);
+        assert!(pruning.late_move_reduction(8 * ONE_PLY, 100) > pruning.late_move_reduction(8 * ONE_PLY, 10));
+        assert!(pruning.late_move_reduction(8 * ONE_PLY, 100) > pruning.late_move_reduction(4 * ONE_PLY, 100));
+
+        // At least one ply is left to search after the reduction.
+        assert_eq!(pruning.late_move_reduction(3 * ONE_PLY, 200), ONE_PLY);
+
+        assert_eq!(Pruning::none().late_move_reduction(20 * ONE_PLY, 200), 0);
+    }
+
+    #[test]
+    fn test_futility_margin() {
+        use ai::ONE_PLY;
+        use ai::pruning::Pruning;
+
+        let pruning = Pruning::default();
+        assert_eq!(pruning.futility_margin(2 * ONE_PLY), Some(2 * pruning.futility_margin));
+        assert_eq!(pruning.futility_margin(3 * ONE_PLY), None);
+        assert_eq!(Pruning::none().futility_margin(ONE_PLY), None);
+    }
+}
@@ -0,0 +1,307 @@
+//! The endgame race.
+//!
+//! Once the stones of the two players passed each other, the game usually becomes a race:
+//! whoever needs fewer moves to fill its target area with its own stones wins, the player to move
+//! if both need the same number. It is not certain though, since stones may still go back and
+//! block the opponent, so races are only used to evaluate positions, not to end the search.
+//!
+//! The number of moves a player needs is bounded from above by a greedy playout, which always
+//! makes the move advancing a stone the furthest. It is bounded from below by an iterative
+//! deepening A* search (IDA*) over the player's own stones, which counts the empty holes of the
+//! target area as the moves still needed, since every move fills at most one of them. The search
+//! gives up after a fixed number of nodes, so short races are solved exactly, while longer ones
+//! are only decided if the bounds of the two players do not overlap.
+//!
+//! The stones of the opponent are ignored, assuming that going back to jump over them never pays
+//! off.
+
+use std::collections::HashMap;
+
+use WinRule;
+use ai::Score;
+use ai::bitboard::Bitboard;
+use ai::internal_game_state::InternalGameState;
+
+/// The number of nodes the search visits at most to tighten the bounds of one arrangement of
+/// stones.
+const SEARCH_NODES: usize = 1000;
+
+/// The number of moves after which a greedy playout gives up.
+const MAX_PLAYOUT_MOVES: usize = 200;
+
+/// The number of arrangements of stones remembered, before all of them are forgotten at once.
+const CACHE_SIZE: usize = 1 << 16;
+
+/// The score of being a move ahead in a race. It is about as much as the evaluation of other
+/// positions changes by a move.
+const MOVE_SCORE: Score = 20_000;
+
+/// The number of holes of the target area of `player` not occupied by its stones.
+fn holes(board: &InternalGameState, player: u8) -> usize {
+    (board.targets[player as usize] & !board.pieces[player as usize]).popcount()
+}
+
+/// Whether the stones of the two players passed each other, so the game is a race.
+///
+/// Races are only run under the win rules which end the game when a target area is filled and
+/// by players who can fill their target area with their own stones.
+pub fn disengaged(state: &InternalGameState) -> bool {
+    if let WinRule::PartialFill { .. } = state.win_rule {
+        return false;
+    }
+
+    for player in 0..2 {
+        let pieces = state.pieces[player];
+        if pieces.popcount() < state.targets[player].popcount() || !(pieces & state.targets[1 - player]).is_empty() {
+            return false;
+        }
+    }
+
+    // The last stone of player 0 has to be ahead of the first stone of player 1.
+    let last = state.pieces[0].ones().map(|index| state.progress(0, index)).min();
+    let first = state.pieces[1].ones().map(|index| -state.progress(1, index)).max();
+    match (last, first) {
+        (Some(last), Some(first)) => last > first,
+        _ => false,
+    }
+}
+
+/// The number of moves a player needs to fill its target area.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RaceBounds {
+    pub lower: usize,
+    /// `None` if the greedy playout did not fill the target area.
+    pub upper: Option<usize>,
+}
+
+impl RaceBounds {
+    /// The number of moves needed, if the bounds met.
+    pub fn exact(&self) -> Option<usize> {
+        if self.upper == Some(self.lower) { self.upper } else { None }
+    }
+
+    /// The best guess of the number of moves needed.
+    pub fn estimate(&self) -> usize {
+        self.upper.unwrap_or(self.lower)
+    }
+}
+
+/// A race from the point of view of the player to move.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Race {
+    pub own: RaceBounds,
+    pub opponent: RaceBounds,
+}
+
+impl Race {
+    /// Scores the race by the number of moves the player to move is ahead, with half a move for
+    /// being the one to move.
+    pub fn evaluate(&self) -> Score {
+        MOVE_SCORE * (self.opponent.estimate() as Score - self.own.estimate() as Score) + MOVE_SCORE / 2
+    }
+}
+
+/// Bounds the number of moves of races. As they do not depend on the stones of the opponent, the
+/// bounds of every arrangement of a player's stones are remembered.
+pub struct RaceSolver {
+    bounds: HashMap<(u8, Bitboard), RaceBounds>,
+    /// The number of moves of greedy playouts from the arrangements they passed.
+    playouts: HashMap<(u8, Bitboard), Option<usize>>,
+    /// The number of nodes visited by the search of the current arrangement.
+    nodes: usize,
+}
+
+impl RaceSolver {
+    pub fn new() -> RaceSolver {
+        RaceSolver {
+            bounds: HashMap::new(),
+            playouts: HashMap::new(),
+            nodes: 0,
+        }
+    }
+
+    /// Forgets the bounds and playouts of all arrangements, so the results of a search do not
+    /// depend on earlier ones.
+    pub fn clear(&mut self) {
+        self.bounds.clear();
+        self.playouts.clear();
+    }
+
+    /// The race of `state`, if it is one.
+    pub fn race(&mut self, state: &InternalGameState) -> Option<Race> {
+        if !disengaged(state) {
+            return None;
+        }
+
+        let player = state.current_player;
+        Some(Race {
+            own: self.bounds(state, player),
+            opponent: self.bounds(state, 1 - player),
+        })
+    }
+
+    /// The number of moves `player` needs to fill its target area in `state`.
+    pub fn bounds(&mut self, state: &InternalGameState, player: u8) -> RaceBounds {
+        let key = (player, state.pieces[player as usize]);
+        if let Some(&bounds) = self.bounds.get(&key) {
+            return bounds;
+        }
+
+        let mut board = *state;
+        board.pieces[1 - player as usize] = Bitboard::default();
+        let mut bounds = RaceBounds {
+            lower: holes(&board, player),
+            upper: self.playout(&mut board, player),
+        };
+
+        self.nodes = 0;
+        while bounds.exact().is_none() {
+            match self.search(&mut board, player, bounds.lower) {
+                Some(true) => bounds.upper = Some(bounds.lower),
+                Some(false) => bounds.lower += 1,
+                None => break,
+            }
+        }
+
+        if self.bounds.len() >= CACHE_SIZE {
+            self.bounds.clear();
+        }
+        self.bounds.insert(key, bounds);
+        bounds
+    }
+
+    /// The number of moves a greedy playout needs to fill the target area of `player`. It always
+    /// makes the move advancing a stone the most rows, moving the stone furthest behind on ties.
+    fn playout(&mut self, board: &mut InternalGameState, player: u8) -> Option<usize> {
+        let start = board.pieces[player as usize];
+        let mut passed = Vec::new();
+        let moves = loop {
+            let pieces = board.pieces[player as usize];
+            if let Some(&moves) = self.playouts.get(&(player, pieces)) {
+                break moves.map(|moves| moves + passed.len());
+            }
+            if holes(board, player) == 0 {
+                break Some(passed.len());
+            }
+            if passed.len() == MAX_PLAYOUT_MOVES {
+                break None;
+            }
+            passed.push(pieces);
+
+            let best = pieces.ones()
+                .flat_map(|from| board.reachable_from(from).ones().map(move |to| (from, to)))
+                .filter(|&(from, to)| board.progress(player, to) > board.progress(player, from))
+                .max_by_key(|&(from, to)| (board.progress(player, to) - board.progress(player, from), -board.progress(player, from)));
+            match best {
+                Some((from, to)) => {
+                    board.pieces[player as usize].unset_bit(from);
+                    board.pieces[player as usize].set_bit(to);
+                }
+                None => break None,
+            }
+        };
+
+        if self.playouts.len() + passed.len() >= CACHE_SIZE {
+            self.playouts.clear();
+        }
+        for (i, &pieces) in passed.iter().enumerate() {
+            self.playouts.insert((player, pieces), moves.map(|moves| moves - i));
+        }
+        board.pieces[player as usize] = start;
+        moves
+    }
+
+    /// Whether `player` can fill its target area within `moves` moves, `None` if the search ran
+    /// out of nodes before it found out.
+    fn search(&mut self, board: &mut InternalGameState, player: u8, moves: usize) -> Option<bool> {
+        let holes = holes(board, player);
+        if holes == 0 {
+            return Some(true);
+        }
+        if holes > moves {
+            return Some(false);
+        }
+        if self.nodes == SEARCH_NODES {
+            return None;
+        }
+        self.nodes += 1;
+
+        let target = board.targets[player as usize];
+        let mut children = Vec::new();
+        for from in board.pieces[player as usize].ones() {
+            // Without moves to spare, every move has to bring another stone into the target area.
+            if holes == moves && target.get_bit(from) {
+                continue;
+            }
+
+            for to in board.reachable_from(from).ones() {
+                let holes_after = holes + target.get_bit(from) as usize - target.get_bit(to) as usize;
+                if holes_after < moves {
+                    children.push((board.progress(player, to) - board.progress(player, from), from, to));
+                }
+            }
+        }
+
+        // The moves advancing the furthest are the most likely to fill the target area in time.
+        children.sort_by_key(|&(advance, _, _)| -advance);
+        for (_, from, to) in children {
+            board.pieces[player as usize].unset_bit(from);
+            board.pieces[player as usize].set_bit(to);
+            let result = self.search(board, player, moves - 1);
+            board.pieces[player as usize].unset_bit(to);
+            board.pieces[player as usize].set_bit(from);
+
+            if result != Some(false) {
+                return result;
+            }
+        }
+
+        Some(false)
+    }
+}
+
+mod tests {
+    #[test]
+    fn test_race() {
+        use {GameState, Geometry, WinRule};
+        use ai::internal_game_state::InternalGameState;
+        use ai::race::{RaceBounds, RaceSolver, disengaged};
+        use builder::PositionBuilder;
+
+        // Player 0 fills its target area with a single step, player 1 needs two.
+        let mut builder = PositionBuilder::new(Geometry::Star, 2);
+        for player in 0..2 {
+            let hole = if player == 0 { (6, 12) } else { (6, 4) };
+            let targets = GameState::default().targets(player);
+            assert!(targets.contains(&hole));
+            for &(x, y) in targets.iter().filter(|&&target| target != hole) {
+                builder.place(x, y, player);
+            }
+        }
+        builder.place(7, 11, 0).place(6, 6, 1);
+
+        let mut solver = RaceSolver::new();
+        let state = InternalGameState::from(builder.build().unwrap());
+        assert!(disengaged(&state));
+        assert_eq!(solver.bounds(&state, 0), RaceBounds { lower: 1, upper: Some(1) });
+        assert_eq!(solver.bounds(&state, 1), RaceBounds { lower: 2, upper: Some(2) });
+        assert!(solver.race(&state).unwrap().evaluate() > 0);
+
+        let state = InternalGameState::from(builder.clone().current_player(1).build().unwrap());
+        let race = solver.race(&state).unwrap();
+        assert_eq!(race.own, RaceBounds { lower: 2, upper: Some(2) });
+        assert!(race.evaluate() < 0);
+
+        solver.clear();
+        assert_eq!(solver.bounds(&state, 0), RaceBounds { lower: 1, upper: Some(1) });
+
+        // With a move limit, the race may not be over in time.
+        let state = InternalGameState::from(builder.clone().win_rule(WinRule::PartialFill { ply: 100 }).build().unwrap());
+        assert!(!disengaged(&state));
+
+        // A stone of player 1 left behind is still in the way of player 0.
+        let state = InternalGameState::from(builder.clone().place(6, 13, 1).remove(6, 6).build().unwrap());
+        assert!(!disengaged(&state));
+        assert_eq!(solver.race(&InternalGameState::from(GameState::default())), None);
+    }
+}
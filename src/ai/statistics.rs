@@ -0,0 +1,168 @@
+//! Reports about the progress and the result of a search.
+
+use std::fmt;
+use std::time::Duration;
+
+use Move;
+
+pub(crate) fn seconds(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + f64::from(duration.subsec_nanos()) / 1_000_000_000.0
+}
+
+/// Divides `numerator` by `denominator`, giving 0 instead of NaN or infinity if the denominator
+/// is 0, e.g. for a search which was stopped immediately.
+pub(crate) fn ratio(numerator: f64, denominator: f64) -> f64 {
+    if denominator == 0.0 {
+        0.0
+    } else {
+        numerator / denominator
+    }
+}
+
+/// Writes a score, or the distance to a forced win or loss.
+fn write_score(f: &mut fmt::Formatter, score: isize, win_in: Option<isize>) -> fmt::Result {
+    match win_in {
+        Some(plies) if plies >= 0 => write!(f, "win in {} plies", plies),
+        Some(plies) => write!(f, "loss in {} plies", -plies),
+        None => write!(f, "score {}", score),
+    }
+}
+
+/// A report sent after every finished iteration of a search.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Progress {
+    /// The depth of the iteration in plies.
+    pub depth: usize,
+    pub score: isize,
+    /// The number of plies to a forced win, negative for a forced loss.
+    pub win_in: Option<isize>,
+    /// The number of nodes visited by the search so far, without helper threads.
+    pub nodes: usize,
+    pub nodes_per_second: f64,
+    pub elapsed: Duration,
+    /// The share of transposition table lookups which found an entry, between 0 and 1.
+    pub tt_hit_rate: f64,
+    /// The line the search expects to be played, starting with its best move.
+    pub pv: Vec<Move>,
+}
+
+impl fmt::Display for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "depth {} ", self.depth)?;
+        write_score(f, self.score, self.win_in)?;
+        write!(f, " nodes {} nps {:.0} time {:.3} tthits {:.2}% pv",
+               self.nodes, self.nodes_per_second, seconds(self.elapsed), 100.0 * self.tt_hit_rate)?;
+        for mov in &self.pv {
+            write!(f, " {}", mov)?;
+        }
+        Ok(())
+    }
+}
+
+/// One of the best lines found by a search.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Line {
+    pub score: isize,
+    /// The number of plies to a forced win, negative for a forced loss.
+    pub win_in: Option<isize>,
+    /// The moves of the line, starting with the move of the root position it is about.
+    pub pv: Vec<Move>,
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_score(f, self.score, self.win_in)?;
+        write!(f, " pv")?;
+        for mov in &self.pv {
+            write!(f, " {}", mov)?;
+        }
+        Ok(())
+    }
+}
+
+/// The statistics of a finished search.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Statistics {
+    /// The depth of the last finished iteration in plies.
+    pub depth: usize,
+    pub score: isize,
+    /// The number of plies to a forced win, negative for a forced loss.
+    pub win_in: Option<isize>,
+    pub nodes: usize,
+    pub leaf_nodes: usize,
+    /// The number of nodes visited by helper threads and pondering.
+    pub helper_nodes: usize,
+    pub cutoffs: usize,
+    pub tt_lookups: usize,
+    pub tt_hits: usize,
+    pub pv_nullsearches: usize,
+    pub pv_failed_nullsearches: usize,
+    /// The number of times the root was searched again because the score fell outside of the
+    /// aspiration window.
+    pub aspiration_researches: usize,
+    /// The number of moves searched again with the full depth after a reduced search failed high.
+    pub reduction_researches: usize,
+    /// The number of nodes pruned because their static evaluation was far from the window.
+    pub futility_prunes: usize,
+    /// The number of static evaluations which scored the position as a race.
+    pub races: usize,
+    pub elapsed: Duration,
+}
+
+impl Statistics {
+    pub fn interior_nodes(&self) -> usize {
+        self.nodes - self.leaf_nodes
+    }
+
+    pub fn nodes_per_second(&self) -> f64 {
+        ratio(self.nodes as f64, seconds(self.elapsed))
+    }
+
+    /// The share of transposition table lookups which found an entry, between 0 and 1.
+    pub fn tt_hit_rate(&self) -> f64 {
+        ratio(self.tt_hits as f64, self.tt_lookups as f64)
+    }
+}
+
+impl fmt::Display for Statistics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let secs = seconds(self.elapsed);
+        let interior_nodes = self.interior_nodes();
+        writeln!(f, "  nodes | total   {} ({:.3} knodes/s)", self.nodes, self.nodes_per_second() / 1000.0)?;
+        writeln!(f, "        | leaf    {} ({:.2}%)", self.leaf_nodes, 100.0 * ratio(self.leaf_nodes as f64, self.nodes as f64))?;
+        writeln!(f, "        | inner   {} ({:.2}%)", interior_nodes, 100.0 * ratio(interior_nodes as f64, self.nodes as f64))?;
+        writeln!(f, "        | helpers {} ({:.3} knodes/s)", self.helper_nodes, ratio(self.helper_nodes as f64, secs) / 1000.0)?;
+        writeln!(f, "cutoffs | total   {} ({:.2}%)", self.cutoffs, 100.0 * ratio(self.cutoffs as f64, interior_nodes as f64))?;
+        writeln!(f, "     TT | lookups {}", self.tt_lookups)?;
+        writeln!(f, "        | hits    {} ({:.2}%)", self.tt_hits, 100.0 * self.tt_hit_rate())?;
+        writeln!(f, "     PV | 0-wind. {}", self.pv_nullsearches)?;
+        writeln!(f, "        | failed  {} ({:.2}%)", self.pv_failed_nullsearches, 100.0 * ratio(self.pv_failed_nullsearches as f64, self.pv_nullsearches as f64))?;
+        writeln!(f, "        | aspir.  {}", self.aspiration_researches)?;
+        writeln!(f, "        | LMR     {}", self.reduction_researches)?;
+        writeln!(f, "pruning | futile  {}", self.futility_prunes)?;
+        writeln!(f, "   race | scored  {}", self.races)?;
+        writeln!(f)?;
+        writeln!(f, "Depth: {}", self.depth)?;
+        writeln!(f, "Time:  {}:{}", self.elapsed.as_secs() / 60, secs % 60.0)?;
+        write!(f, "Score: {}", self.score)?;
+        match self.win_in {
+            Some(plies) if plies >= 0 => write!(f, " (win in {} plies)", plies),
+            Some(plies) => write!(f, " (loss in {} plies)", -plies),
+            None => Ok(()),
+        }
+    }
+}
+
+mod tests {
+    #[test]
+    fn test_empty_search() {
+        use ai::statistics::Statistics;
+
+        let statistics = Statistics::default();
+        assert_eq!(statistics.tt_hit_rate(), 0.0);
+        assert_eq!(statistics.nodes_per_second(), 0.0);
+
+        let text = statistics.to_string();
+        assert!(!text.contains("NaN") && !text.contains("inf"));
+    }
+}
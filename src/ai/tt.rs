@@ -1,3 +1,5 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
 use ai::{Depth, IncrementalHash, Score};
 use ai::internal_game_state::{InternalMove, Ply};
 
@@ -8,6 +10,17 @@ pub enum ScoreType {
     UpperBound(Score)
 }
 
+impl ScoreType {
+    /// Applies `f` to the score, keeping its kind.
+    pub fn map<F: Fn(Score) -> Score>(self, f: F) -> ScoreType {
+        match self {
+            ScoreType::Exact(score) => ScoreType::Exact(f(score)),
+            ScoreType::LowerBound(score) => ScoreType::LowerBound(f(score)),
+            ScoreType::UpperBound(score) => ScoreType::UpperBound(f(score)),
+        }
+    }
+}
+
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub struct Transposition {
     pub evaluation: ScoreType,
@@ -17,6 +30,41 @@ pub struct Transposition {
 }
 
 impl Transposition {
+    /// Packs the transposition into two words. The kind of the score is stored as 1 to 3, so
+    /// that empty entries, which are all zeros, can be told apart.
+    fn pack(&self) -> [u64; 2] {
+        let (kind, score) = match self.evaluation {
+            ScoreType::Exact(score) => (1, score),
+            ScoreType::LowerBound(score) => (2, score),
+            ScoreType::UpperBound(score) => (3, score),
+        };
+
+        [
+            (score as i32 as u32 as u64) << 32 | self.depth as u32 as u64,
+            (self.ply as u64) << 32 | kind << 16 | (self.best_move.from as u64) << 8 | self.best_move.to as u64,
+        ]
+    }
+
+    fn unpack(data: [u64; 2]) -> Option<Transposition> {
+        let score = (data[0] >> 32) as u32 as i32 as Score;
+        let evaluation = match (data[1] >> 16) & 0xFF {
+            1 => ScoreType::Exact(score),
+            2 => ScoreType::LowerBound(score),
+            3 => ScoreType::UpperBound(score),
+            _ => return None,
+        };
+
+        Some(Transposition {
+            evaluation,
+            best_move: InternalMove {
+                from: (data[1] >> 8) as u8,
+                to: data[1] as u8,
+            },
+            depth: data[0] as u32 as i32 as Depth,
+            ply: (data[1] >> 32) as Ply,
+        })
+    }
+
     pub fn should_be_replaced_by(&self, other: &Self, pv: bool) -> bool {
         if pv {
             return true;
@@ -34,9 +82,15 @@ impl Transposition {
     }
 }
 
+/// A transposition table which can be shared by several search threads without locking.
+///
+/// Every entry consists of two words of data and a key, which is the hash of the position XORed
+/// with both data words. Entries are written and read word by word, so another thread may
+/// interleave its writes with ours, leaving a mix of two entries. Such an entry does not verify
+/// against either hash anymore and is treated as missing.
 pub struct TranspositionTable {
-    table: Vec<Option<Transposition>>,
-    bitmask: usize,
+    table: Vec<[AtomicU64; 3]>,
+    bitmask: u64,
 }
 
 impl TranspositionTable {
@@ -45,21 +99,65 @@ impl TranspositionTable {
         let mut table = Vec::with_capacity(size);
 
         for _ in 0..size {
-            table.push(None);
+            table.push([AtomicU64::new(0), AtomicU64::new(0), AtomicU64::new(0)]);
         }
 
         TranspositionTable {
             table,
-            bitmask: size - 1,
+            bitmask: size as u64 - 1,
+        }
+    }
+
+    pub fn clear(&self) {
+        for entry in &self.table {
+            for word in entry {
+                word.store(0, Ordering::Relaxed);
+            }
         }
     }
 
     pub fn get(&self, hash: IncrementalHash) -> Option<Transposition> {
-        self.table[hash & self.bitmask]
+        let entry = &self.table[(hash & self.bitmask) as usize];
+        let key = entry[0].load(Ordering::Relaxed);
+        let data = [entry[1].load(Ordering::Relaxed), entry[2].load(Ordering::Relaxed)];
+        if key ^ data[0] ^ data[1] != hash {
+            return None;
+        }
+
+        Transposition::unpack(data)
     }
 
-    pub fn insert(&mut self, hash: IncrementalHash, transposition: Transposition) {
-        self.table[hash & self.bitmask] = Some(transposition);
+    pub fn insert(&self, hash: IncrementalHash, transposition: Transposition) {
+        let entry = &self.table[(hash & self.bitmask) as usize];
+        let data = transposition.pack();
+        entry[0].store(hash ^ data[0] ^ data[1], Ordering::Relaxed);
+        entry[1].store(data[0], Ordering::Relaxed);
+        entry[2].store(data[1], Ordering::Relaxed);
     }
 }
 
+mod tests {
+    #[test]
+    fn test_tt() {
+        use ai::internal_game_state::InternalMove;
+        use ai::tt::{ScoreType, Transposition, TranspositionTable};
+
+        let tt = TranspositionTable::new(4);
+        let transposition = Transposition {
+            evaluation: ScoreType::LowerBound(-123_456_789),
+            best_move: InternalMove { from: 0x12, to: 0xFE },
+            depth: 7500,
+            ply: 300,
+        };
+
+        assert_eq!(tt.get(0), None);
+        tt.insert(0x1234_5678, transposition);
+        assert_eq!(tt.get(0x1234_5678), Some(transposition));
+
+        // Same index, but a different position.
+        assert_eq!(tt.get(0x1234_5670), None);
+
+        tt.clear();
+        assert_eq!(tt.get(0x1234_5678), None);
+    }
+}
@@ -5,9 +5,23 @@ use std::io::{self, BufRead};
 
 use halma::*;
 use halma::ai::{AI, StopCondition};
+use halma::ai::extensions::Extensions;
+use halma::ai::pruning::Pruning;
+use halma::ai::statistics::Progress;
+use halma::clock::Clock;
+
+/// Prints every progress report as a line `info <json>` before the response to `getmove`.
+fn print_progress(progress: &Progress) {
+    println!("info {}", serde_json::to_string(progress).unwrap());
+}
 
 fn main() {
     let mut ai = AI::new(GameState::default());
+    // Kept for positions set up later, so a run can be reproduced.
+    let mut seed = None;
+    let mut progress = false;
+    let mut pruning = Pruning::default();
+    let mut extensions = Extensions::default();
 
     let stdin = io::stdin();
     let lock = stdin.lock();
@@ -17,14 +31,68 @@ fn main() {
             break;
         } else if line.starts_with("setup ") {
             ai = AI::new(serde_json::from_str(&line.trim_left_matches("setup ")).unwrap());
+            if let Some(seed) = seed {
+                ai.set_seed(seed);
+                ai.deterministic = true;
+            }
+            if progress {
+                ai.set_progress(Some(Box::new(print_progress)));
+            }
+            ai.pruning = pruning;
+            ai.extensions = extensions;
+            println!("ok");
+        } else if line.starts_with("seed ") {
+            let value = line.trim_start_matches("seed ").parse().unwrap();
+            ai.set_seed(value);
+            ai.deterministic = true;
+            seed = Some(value);
+            println!("ok");
+        } else if line.starts_with("nodes ") {
+            ai.stop_condition = StopCondition::Nodes(line.trim_start_matches("nodes ").parse().unwrap());
+            println!("ok");
+        } else if line.starts_with("threads ") {
+            ai.threads = line.trim_start_matches("threads ").parse().unwrap();
+            println!("ok");
+        } else if line.starts_with("progress ") {
+            progress = line.trim_start_matches("progress ") == "on";
+            ai.set_progress(if progress { Some(Box::new(print_progress)) } else { None });
             println!("ok");
+        } else if line.starts_with("pruning ") {
+            pruning = if line.trim_start_matches("pruning ") == "off" { Pruning::none() } else { Pruning::default() };
+            ai.pruning = pruning;
+            println!("ok");
+        } else if line.starts_with("extensions ") {
+            extensions = if line.trim_start_matches("extensions ") == "off" { Extensions::none() } else { Extensions::default() };
+            ai.extensions = extensions;
+            println!("ok");
+        } else if line.starts_with("pv") {
+            println!("{}", serde_json::to_string(&ai.principal_variation()).unwrap());
+        } else if line.starts_with("multipv ") {
+            ai.multi_pv = line.trim_start_matches("multipv ").parse().unwrap();
+            println!("ok");
+        } else if line.starts_with("lines") {
+            println!("{}", serde_json::to_string(ai.lines()).unwrap());
+        } else if line.starts_with("stats") {
+            println!("{}", serde_json::to_string(ai.statistics()).unwrap());
         } else if line.starts_with("move ") {
             let mov = serde_json::from_str(&line.trim_left_matches("move ")).unwrap();
             ai.make_move(mov);
             println!("ok");
+        } else if line.starts_with("ponder") {
+            match ai.start_pondering() {
+                Some(reply) => println!("{}", serde_json::to_string(&reply).unwrap()),
+                None => println!("none"),
+            }
+        } else if line.starts_with("stop") {
+            ai.stop_pondering();
+            println!("ok");
         } else if line.starts_with("getmove") {
             let mov = ai.calculate_move();
             println!("{}", serde_json::to_string(&mov).unwrap());
+        } else if line.starts_with("clock ") {
+            let clock: Clock = serde_json::from_str(line.trim_start_matches("clock ")).unwrap();
+            ai.stop_condition = StopCondition::for_clock(&clock, ai.state.current_player);
+            println!("ok");
         } else if line.starts_with("millis ") {
             let millis: u64 = line.trim_left_matches("millis ").parse().unwrap();
             ai.stop_condition = StopCondition::Time(::std::time::Duration::new(millis / 1000, (millis % 1000) as u32*1_000_000));
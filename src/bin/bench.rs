@@ -0,0 +1,61 @@
+//! Measures the time the AI needs to search a few positions to a fixed depth with different
+//! numbers of threads and prints the speedup over a single thread.
+
+extern crate halma;
+
+use std::time::{Duration, Instant};
+
+use halma::*;
+use halma::ai::{AI, StopCondition};
+
+/// The depth every position is searched to.
+const DEPTH: i32 = 6;
+
+/// The thread counts which are compared.
+const THREADS: [usize; 5] = [1, 2, 4, 8, 16];
+
+/// The positions which are searched: the start position and positions after some opening moves.
+fn positions() -> Vec<GameState> {
+    let openings: [&[Move]; 3] = [
+        &[],
+        &[Move { from: (6, 4), to: (6, 5) }, Move { from: (6, 12), to: (6, 11) }],
+        &[Move { from: (6, 4), to: (6, 5) }, Move { from: (6, 12), to: (6, 11) },
+          Move { from: (5, 4), to: (6, 6) }, Move { from: (5, 12), to: (6, 10) }],
+    ];
+
+    openings.iter().map(|moves| {
+        let mut game = Game::default();
+        for &mov in moves.iter() {
+            game.try_move(mov).unwrap();
+        }
+
+        *game.state()
+    }).collect()
+}
+
+fn time_to_depth(state: &GameState, threads: usize) -> Duration {
+    let mut ai = AI::new(*state);
+    ai.stop_condition = StopCondition::Depth(DEPTH);
+    ai.threads = threads;
+
+    let start = Instant::now();
+    ai.calculate_move();
+    start.elapsed()
+}
+
+fn seconds(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + f64::from(duration.subsec_nanos()) / 1e9
+}
+
+fn main() {
+    let positions = positions();
+    let mut single = 0.0;
+    println!("threads | time (s) | speedup");
+    for &threads in THREADS.iter() {
+        let total: f64 = positions.iter().map(|state| seconds(time_to_depth(state, threads))).sum();
+        if threads == 1 {
+            single = total;
+        }
+        println!("{:>7} | {:>8.3} | {:>7.2}", threads, total, single / total);
+    }
+}
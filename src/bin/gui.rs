@@ -6,8 +6,61 @@ use sdl2::keyboard::Keycode;
 use sdl2::pixels::Color;
 use sdl2::gfx::primitives::DrawRenderer;
 
+use std::time::{Duration, Instant};
+
 use halma::*;
 use halma::ai::{AI, StopCondition};
+use halma::clock::Clock;
+
+/// The time control of games played in the GUI.
+const TIME_CONTROL: &str = "300+2";
+
+fn new_game() -> Game {
+    let mut game = Game::default();
+    game.set_clock(Some(Clock::new(TIME_CONTROL.parse().unwrap(), 2)));
+    game
+}
+
+/// Creates the AIs of both players for the current position of `game`. If `verbose` is set, the
+/// first one reports the progress of its searches.
+fn new_ais(game: &Game, verbose: bool) -> (AI, AI) {
+    let mut ai0 = AI::new(*game.state());
+    set_verbose(&mut ai0, verbose);
+    let ai1 = AI::new(*game.state());
+    (ai0, ai1)
+}
+
+/// Sets whether `ai` prints the progress of its searches.
+fn set_verbose(ai: &mut AI, verbose: bool) {
+    if verbose {
+        ai.set_progress(Some(Box::new(|progress| println!("{}", progress))));
+    } else {
+        ai.set_progress(None);
+    }
+}
+
+/// Plays `mov` on the clock, which started for the current move at `turn_start`, and informs
+/// both AIs about it. A rejected move is reported and both AIs are resynchronized with the game.
+fn play_move(game: &mut Game, ai0: &mut AI, ai1: &mut AI, turn_start: &mut Instant, verbose: bool, mov: Move) {
+    match game.try_timed_move(mov, turn_start.elapsed()) {
+        Ok(()) => {
+            ai0.make_move(mov);
+            ai1.make_move(mov);
+        }
+        Err(error) => {
+            eprintln!("Rejected move {:?}: {}", mov, error);
+            let (new_ai0, new_ai1) = new_ais(game, verbose);
+            *ai0 = new_ai0;
+            *ai1 = new_ai1;
+        }
+    }
+    *turn_start = Instant::now();
+}
+
+/// Formats a clock time as minutes and seconds.
+fn format_time(time: Duration) -> String {
+    format!("{}:{:02}", time.as_secs() / 60, time.as_secs() % 60)
+}
 
 fn draw_tile(tile: Tile, canvas: &mut sdl2::render::WindowCanvas, board_x: i8, board_y: i8) {
     let (draw_x, draw_y) = board_space_to_screen_space(board_x, board_y);
@@ -29,14 +82,18 @@ fn player_color(id: u8) -> Color {
     match id {
         0 => Color::RGB(255, 0, 0),
         1 => Color::RGB(0, 0, 255),
-        _ => unimplemented!()
+        2 => Color::RGB(0, 160, 0),
+        3 => Color::RGB(224, 160, 0),
+        4 => Color::RGB(160, 0, 160),
+        5 => Color::RGB(0, 160, 160),
+        _ => Color::RGB(96, 96, 96),
     }
 }
 
 
 fn draw_board(canvas: &mut sdl2::render::WindowCanvas, state: &GameState) {
     for y in 0..BOARD_HEIGHT as i8 {
-        for x in 0..BOARD_WIDTH as i8 {
+        for x in 0..MAX_BOARD_WIDTH as i8 {
             let tile = state.get(x, y);
             canvas.set_draw_color(Color::RGB(0, 0, 0));
             draw_tile(tile, canvas, x, y);
@@ -44,6 +101,19 @@ fn draw_board(canvas: &mut sdl2::render::WindowCanvas, state: &GameState) {
     }
 }
 
+/// Draws the moves of a principal variation starting in `state` as arrows in the colors of the
+/// players making them.
+fn draw_plan(canvas: &mut sdl2::render::WindowCanvas, state: &GameState, moves: &[Move]) {
+    for (i, mov) in moves.iter().enumerate() {
+        let player = (state.current_player() as usize + i) % state.num_players() as usize;
+        let (fx, fy) = board_space_to_screen_space(mov.from.0, mov.from.1);
+        let (tx, ty) = board_space_to_screen_space(mov.to.0, mov.to.1);
+        canvas.set_draw_color(player_color(player as u8));
+        canvas.draw_line((fx, fy), (tx, ty)).unwrap();
+        canvas.fill_rect(sdl2::rect::Rect::new(tx-3, ty-3, 6, 6)).unwrap();
+    }
+}
+
 fn board_space_to_screen_space(x: i8, y: i8) -> (i32, i32) {
     let screen_y = 10 + y as i32*20;
     let screen_x = if y % 2 == 0 {
@@ -65,7 +135,7 @@ fn nearest_board_position(state: &GameState, x: i32, y: i32) -> Option<(i8, i8)>
     let mut min_d = None;
 
     for by in 0..BOARD_HEIGHT as i8 {
-        for bx in 0..BOARD_WIDTH as i8 {
+        for bx in 0..MAX_BOARD_WIDTH as i8 {
             if state.get(bx, by) == Tile::Invalid {
                 continue;
             }
@@ -99,19 +169,21 @@ fn main() {
     canvas.clear();
     canvas.present();
 
-    let mut game: Game = Default::default();
+    let mut game = new_game();
+    let mut turn_start = Instant::now();
     let mut mouse_x = 0;
     let mut mouse_y = 0;
     let mut selection = None;
     let mut display_moves = false;
+    // The principal variation of the first AI's last search and the ply it continues from.
+    let mut plan: Option<(usize, Vec<Move>)> = None;
+    let mut display_plan = false;
+    // Whether the first AI prints the progress and statistics of its searches.
+    let mut verbose = false;
 
     let mut events = sdl.event_pump().unwrap();
 
-    let mut ai0 = AI::new(*game.state());
-    ai0.print_statistics = true;
-    ai0.stop_condition = StopCondition::Time(::std::time::Duration::from_secs(1));
-    let mut ai1 = AI::new(*game.state());
-    ai1.stop_condition = StopCondition::Time(::std::time::Duration::from_secs(1));
+    let (mut ai0, mut ai1) = new_ais(&game, verbose);
 
     let mut autoplay0 = true;
     let mut autoplay1 = true;
@@ -120,22 +192,54 @@ fn main() {
         canvas.set_draw_color(Color::RGB(224, 224, 224));
         canvas.clear();
 
+        let mut navigated = false;
         for event in events.poll_iter() {
             match event {
                 Event::Quit {..} | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => break 'mainloop,
-                Event::KeyDown { keycode: Some(Keycode::R), .. } => game = Default::default(),
+                Event::KeyDown { keycode: Some(Keycode::R), .. } => {
+                    game = new_game();
+                    let (new_ai0, new_ai1) = new_ais(&game, verbose);
+                    ai0 = new_ai0;
+                    ai1 = new_ai1;
+                    turn_start = Instant::now();
+                }
                 Event::KeyDown { keycode: Some(Keycode::M), .. } => display_moves = !display_moves,
-                Event::KeyDown { keycode: Some(Keycode::U), .. } => game.undo(),
+                Event::KeyDown { keycode: Some(Keycode::P), .. } => display_plan = !display_plan,
+                Event::KeyDown { keycode: Some(Keycode::V), .. } => {
+                    verbose = !verbose;
+                    set_verbose(&mut ai0, verbose);
+                }
+                Event::KeyDown { keycode: Some(Keycode::U), .. } |
+                Event::KeyDown { keycode: Some(Keycode::Left), .. } => {
+                    game.undo();
+                    navigated = true;
+                }
+                Event::KeyDown { keycode: Some(Keycode::Right), .. } => {
+                    game.redo();
+                    navigated = true;
+                }
+                Event::KeyDown { keycode: Some(Keycode::Home), .. } => {
+                    game.go_to_start();
+                    navigated = true;
+                }
+                Event::KeyDown { keycode: Some(Keycode::End), .. } => {
+                    game.go_to_end();
+                    navigated = true;
+                }
                 Event::KeyDown { keycode: Some(Keycode::A), .. } => {
-                    let mov;
-                    if game.state().current_player() == 0 {
-                        mov = ai0.calculate_move();
+                    let mov = if game.state().current_player() == 0 {
+                        ai0.stop_condition = StopCondition::for_clock(game.clock().unwrap(), 0);
+                        let mov = ai0.calculate_move();
+                        if verbose {
+                            println!("{}\n", ai0.statistics());
+                        }
+                        plan = Some((game.state().ply() + 1, ai0.principal_variation().split_off(1)));
+                        mov
                     } else {
-                        mov = ai1.calculate_move();
-                    }
-                    game.move_piece(mov);
-                    ai0.make_move(mov);
-                    ai1.make_move(mov);
+                        ai1.stop_condition = StopCondition::for_clock(game.clock().unwrap(), 1);
+                        ai1.calculate_move()
+                    };
+                    play_move(&mut game, &mut ai0, &mut ai1, &mut turn_start, verbose, mov);
                 }
                 Event::MouseMotion { x, y, .. } => {
                     mouse_x = x;
@@ -157,9 +261,7 @@ fn main() {
                             if let Some((bx, by)) = nearest_board_position(game.state(), mouse_x, mouse_y) {
                                 if game.state().moves_from(x, y).contains(&Move { from: (x, y), to: (bx, by) }) {
                                     let mov = Move { from: (x, y), to: (bx, by) };
-                                    game.move_piece(mov);
-                                    ai0.make_move(mov);
-                                    ai1.make_move(mov);
+                                    play_move(&mut game, &mut ai0, &mut ai1, &mut turn_start, verbose, mov);
                                 }
                             }
 
@@ -170,19 +272,32 @@ fn main() {
                 _ => {}
             }
         }
-        
+
+        if navigated {
+            // The AIs only follow the moves played, so they are rebuilt for the position
+            // navigated to.
+            let (new_ai0, new_ai1) = new_ais(&game, verbose);
+            ai0 = new_ai0;
+            ai1 = new_ai1;
+            turn_start = Instant::now();
+        }
+
         if autoplay0 && game.state().current_player() == 0 {
+            ai0.stop_condition = StopCondition::for_clock(game.clock().unwrap(), 0);
             let mov = ai0.calculate_move();
-            game.move_piece(mov);
-            ai0.make_move(mov);
-            ai1.make_move(mov);
+            if verbose {
+                println!("{}\n", ai0.statistics());
+            }
+            plan = Some((game.state().ply() + 1, ai0.principal_variation().split_off(1)));
+            play_move(&mut game, &mut ai0, &mut ai1, &mut turn_start, verbose, mov);
         } else if autoplay1 && game.state().current_player() == 1 {
+            ai1.stop_condition = StopCondition::for_clock(game.clock().unwrap(), 1);
             let mov = ai1.calculate_move();
-            game.move_piece(mov);
-            ai0.make_move(mov);
-            ai1.make_move(mov);
+            play_move(&mut game, &mut ai0, &mut ai1, &mut turn_start, verbose, mov);
         }
 
+        game.check_flag(turn_start.elapsed());
+
         draw_board(&mut canvas, game.state());
 
         if let Some((x, y)) = nearest_board_position(game.state(), mouse_x, mouse_y) {
@@ -220,6 +335,12 @@ fn main() {
             canvas.string(32, 8, &format!("Possible moves: {}", &moves.len()), Color::RGB(0, 0, 0)).unwrap();
         }
 
+        if let Some((ply, ref moves)) = plan {
+            if display_plan && ply == game.state().ply() {
+                draw_plan(&mut canvas, game.state(), moves);
+            }
+        }
+
         if let Some(&last_move) = game.last_move() {
             canvas.set_draw_color(Color::RGB(168, 168, 168));
             let (fx, fy) = last_move.from;
@@ -233,13 +354,31 @@ fn main() {
         canvas.set_draw_color(player_color(game.state().current_player()));
         canvas.fill_rect(Some(sdl2::rect::Rect::new(0, 0, 24, 24))).unwrap();
 
+        match game.termination() {
+            Some(Termination::Won(player)) => canvas.string(32, 328, &format!("Player {} won", player), Color::RGB(0, 0, 0)).unwrap(),
+            Some(Termination::Repetition) => canvas.string(32, 328, "Draw by repetition", Color::RGB(0, 0, 0)).unwrap(),
+            Some(Termination::MoveLimit) => canvas.string(32, 328, "Draw by move limit", Color::RGB(0, 0, 0)).unwrap(),
+            Some(Termination::FlagFall(player)) => canvas.string(32, 328, &format!("Player {} lost on time", player), Color::RGB(0, 0, 0)).unwrap(),
+            None => {}
+        }
+
+        if let Some(clock) = game.clock() {
+            let current_player = game.state().current_player();
+            let times: Vec<_> = (0..2).map(|player| {
+                if player == current_player && game.termination().is_none() {
+                    clock.remaining_after(player, turn_start.elapsed()).unwrap_or_default()
+                } else {
+                    clock.remaining(player)
+                }
+            }).collect();
+            canvas.string(200, 8, &format_time(times[0]), player_color(0)).unwrap();
+            canvas.string(200, 20, &format_time(times[1]), player_color(1)).unwrap();
+        }
+
         canvas.present();
         ::std::thread::sleep(::std::time::Duration::new(0, 1_000_000_000u32 / 60));
 
-        if game.state().won(0) {
-            autoplay0 = false;
-            autoplay1 = false;
-        } else if game.state().won(1) {
+        if game.termination().is_some() {
             autoplay0 = false;
             autoplay1 = false;
         }
@@ -1,14 +1,35 @@
+//! Plays a tournament between engines speaking the protocol of the `ai` binary and prints the
+//! standings after every round.
+//!
+//! Engines are given as pairs of a name and the path of their binary. Each one may be followed by
+//! `--send <command>` options, whose commands are sent to it after every `setup`, so the same
+//! binary can play with different settings, e.g.
+//!
+//! ```text
+//! match --rounds 20 --time-control 5+0.1 pruned target/release/ai full target/release/ai --send "pruning off"
+//! ```
+
 extern crate halma;
 extern crate serde_json;
 
 use std::io::{Read, Write};
 use std::process::{ChildStdin, ChildStdout, Command, Stdio};
+use std::time::Instant;
 
 use halma::*;
+use halma::clock::Clock;
+
+/// The time control of every game of the tournament, unless `--time-control` is given.
+const TIME_CONTROL: &str = "30+0.5";
+
+/// The number of rounds of the tournament, unless `--rounds` is given.
+const ROUNDS: usize = 12;
 
 struct EngineDefinition {
     name: String,
     path: String,
+    /// Sent to the engine after every `setup`, each of them answered with `ok`.
+    commands: Vec<String>,
 }
 
 impl EngineDefinition {
@@ -16,6 +37,7 @@ impl EngineDefinition {
         EngineDefinition {
             name: name.to_owned(),
             path: path.to_owned(),
+            commands: Vec::new(),
         }
     }
 
@@ -24,6 +46,7 @@ impl EngineDefinition {
         Engine {
             stdin: child.stdin.unwrap(),
             stdout: child.stdout.unwrap(),
+            commands: self.commands.clone(),
         }
     }
 }
@@ -31,19 +54,23 @@ impl EngineDefinition {
 struct Engine {
     stdin: ChildStdin,
     stdout: ChildStdout,
+    commands: Vec<String>,
 }
 
 impl Engine {
     fn setup(&mut self, state: &GameState) {
         self.expect_ok_response(format!("setup {}", serde_json::to_string(state).unwrap()));
+        for command in self.commands.clone() {
+            self.expect_ok_response(command);
+        }
     }
 
     fn make_move(&mut self, mov: Move) {
         self.expect_ok_response(format!("move {}", serde_json::to_string(&mov).unwrap()));
     }
 
-    fn millis(&mut self, millis: u64) {
-        self.expect_ok_response(format!("millis {}", millis));
+    fn clock(&mut self, clock: &Clock) {
+        self.expect_ok_response(format!("clock {}", serde_json::to_string(clock).unwrap()));
     }
 
     fn quit(&mut self) {
@@ -70,7 +97,7 @@ impl Engine {
     }
 }
 
-fn run(engines: Vec<EngineDefinition>, rounds: usize) {
+fn run(engines: Vec<EngineDefinition>, rounds: usize, time_control: &str) {
     let mut results: Vec<Vec<Vec<Outcome>>> = Vec::new();
     for round in 0..rounds {
         results.push(Vec::new());
@@ -85,7 +112,7 @@ fn run(engines: Vec<EngineDefinition>, rounds: usize) {
                 let mut ai0 = engine0.spawn();
                 let mut ai1 = engine1.spawn();
 
-                results[round][i].push(run_single(&mut ai0, &mut ai1, 300));
+                results[round][i].push(run_single(&mut ai0, &mut ai1, 300, time_control));
                 ai0.quit();
                 ai1.quit();
             }
@@ -136,84 +163,53 @@ fn run(engines: Vec<EngineDefinition>, rounds: usize) {
     }
 }
 
-enum Outcome {
-    Win,
-    Loss,
-    Draw,
-}
-
-fn run_single(ai0: &mut Engine, ai1: &mut Engine, max_plies: usize) -> Outcome {
+fn run_single(ai0: &mut Engine, ai1: &mut Engine, max_plies: usize, time_control: &str) -> Outcome {
     let mut game = Game::default();
+    game.set_draw_rules(DrawRules { repetitions: Some(3), max_plies: Some(max_plies) });
+    game.set_clock(Some(Clock::new(time_control.parse().unwrap(), 2)));
     ai0.setup(game.state());
     ai1.setup(game.state());
-    ai0.millis(500);
-    ai1.millis(500);
 
-    let mut plies = 0;
     loop {
-        let mov = ai0.getmove();
-        game.move_piece(mov);
-        ai0.make_move(mov);
-        ai1.make_move(mov);
-
-        if game.state().won(0) {
-            return Outcome::Win;
-        }
-
-        plies += 1;
-        if plies > max_plies {
-            return Outcome::Draw;
+        let player = game.state().current_player();
+        let engine = if player == 0 { &mut *ai0 } else { &mut *ai1 };
+        engine.clock(game.clock().unwrap());
+        let start = Instant::now();
+        let mov = engine.getmove();
+
+        // An engine sending an illegal move or running out of time forfeits the game.
+        if game.try_timed_move(mov, start.elapsed()).is_err() {
+            return if player == 0 { Outcome::Loss } else { Outcome::Win };
         }
-
-        let mov = ai1.getmove();
-        game.move_piece(mov);
         ai0.make_move(mov);
         ai1.make_move(mov);
 
-        if game.state().won(1) {
-            return Outcome::Loss;
-        }
-
-        plies += 1;
-        if plies > max_plies {
-            return Outcome::Draw;
+        if let Some(termination) = game.termination() {
+            return termination.outcome(0);
         }
     }
 }
 
 fn main() {
-    let mut names = Vec::new();
-    let mut paths = Vec::new();
-
-    for (i, value) in ::std::env::args().skip(1).enumerate() {
-        if i % 2 == 0 {
-            names.push(value);
-        } else {
-            paths.push(value);
-        }
-    }
-
-    let ais = names.iter().zip(paths).map(|(name, path)| EngineDefinition::new(name, &path)).collect();
-
-    run(ais, 12);
-}
-
-/*
-fn print_board(game: &Game) {
-    for y in 0..BOARD_HEIGHT as i8 {
-        if y % 2 == 0 {
-            print!(" ");
-        }
-
-        for x in 0..BOARD_WIDTH as i8 {
-            match game.state().get(x, y) {
-                Tile::Empty => print!(". "),
-                Tile::Player(i) => print!("{} ", i),
-                _ => print!("  "),
+    let mut engines: Vec<EngineDefinition> = Vec::new();
+    let mut rounds = ROUNDS;
+    let mut time_control = TIME_CONTROL.to_owned();
+
+    let mut args = ::std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--rounds" => rounds = args.next().expect("Missing number of rounds").parse().unwrap(),
+            "--time-control" => time_control = args.next().expect("Missing time control"),
+            "--send" => {
+                let command = args.next().expect("Missing command");
+                engines.last_mut().expect("--send before the first engine").commands.push(command);
+            }
+            name => {
+                let path = args.next().expect("Missing path of engine");
+                engines.push(EngineDefinition::new(name, &path));
             }
         }
-
-        println!("");
     }
+
+    run(engines, rounds, &time_control);
 }
-*/
@@ -0,0 +1,209 @@
+//! Building arbitrary positions
+//!
+//! `PositionBuilder` creates positions other than the start position, e.g. for test fixtures,
+//! puzzles or handicap games, in which some players start with fewer stones.
+
+use std::error;
+use std::fmt;
+
+use {BOARD_HEIGHT, MAX_BOARD_WIDTH, GameState, Geometry, Tile, WinRule};
+
+/// The reason a built position is not valid.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PositionError {
+    /// A stone was placed on a hole that is not part of the board.
+    InvalidLocation(i8, i8),
+    /// A stone or the side to move belongs to a player that does not take part in the game.
+    InvalidPlayer(u8),
+    /// The number of players is not supported by the board.
+    UnsupportedPlayers(u8),
+    /// A player has no stones or more stones than fit into its home.
+    StoneCount(u8, usize),
+    /// The position is already won by the given player.
+    AlreadyWon(u8),
+}
+
+impl fmt::Display for PositionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PositionError::InvalidLocation(x, y) => write!(f, "({}, {}) is not a hole of the board", x, y),
+            PositionError::InvalidPlayer(player) => write!(f, "player {} does not take part in the game", player),
+            PositionError::UnsupportedPlayers(num_players) => write!(f, "unsupported number of players: {}", num_players),
+            PositionError::StoneCount(player, count) => write!(f, "player {} has {} stones", player, count),
+            PositionError::AlreadyWon(player) => write!(f, "player {} has already won", player),
+        }
+    }
+}
+
+impl error::Error for PositionError {}
+
+/// Places and removes stones and sets the remaining properties of a position, which is checked
+/// for validity by `build`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PositionBuilder {
+    geometry: Geometry,
+    num_players: u8,
+    stones: Vec<((i8, i8), u8)>,
+    current_player: u8,
+    ply: usize,
+    win_rule: WinRule,
+}
+
+impl PositionBuilder {
+    /// Starts with an empty board of the given geometry.
+    pub fn new(geometry: Geometry, num_players: u8) -> PositionBuilder {
+        PositionBuilder {
+            geometry,
+            num_players,
+            stones: Vec::new(),
+            current_player: 0,
+            ply: 0,
+            win_rule: WinRule::default(),
+        }
+    }
+
+    /// Starts with the stones, side to move, ply and win rule of `state`.
+    pub fn from_state(state: &GameState) -> PositionBuilder {
+        let mut builder = PositionBuilder::new(state.geometry, state.num_players);
+        for y in 0..BOARD_HEIGHT as i8 {
+            for x in 0..MAX_BOARD_WIDTH as i8 {
+                if let Tile::Player(player) = state.get(x, y) {
+                    builder.stones.push(((x, y), player));
+                }
+            }
+        }
+        builder.current_player = state.current_player;
+        builder.ply = state.ply;
+        builder.win_rule = state.win_rule;
+        builder
+    }
+
+    /// Puts a stone of `player` on `(x, y)`, replacing any stone already there.
+    pub fn place(&mut self, x: i8, y: i8, player: u8) -> &mut PositionBuilder {
+        self.remove(x, y);
+        self.stones.push(((x, y), player));
+        self
+    }
+
+    /// Removes the stone on `(x, y)`, if there is one.
+    pub fn remove(&mut self, x: i8, y: i8) -> &mut PositionBuilder {
+        self.stones.retain(|&(hole, _)| hole != (x, y));
+        self
+    }
+
+    /// Removes all stones.
+    pub fn clear(&mut self) -> &mut PositionBuilder {
+        self.stones.clear();
+        self
+    }
+
+    pub fn current_player(&mut self, player: u8) -> &mut PositionBuilder {
+        self.current_player = player;
+        self
+    }
+
+    pub fn ply(&mut self, ply: usize) -> &mut PositionBuilder {
+        self.ply = ply;
+        self
+    }
+
+    pub fn win_rule(&mut self, win_rule: WinRule) -> &mut PositionBuilder {
+        self.win_rule = win_rule;
+        self
+    }
+
+    /// Creates the position, checking that every stone is on the board, every player has at
+    /// least one and at most as many stones as fit into its home, and nobody has won yet.
+    pub fn build(&self) -> Result<GameState, PositionError> {
+        if !self.geometry.player_counts().contains(&self.num_players) {
+            return Err(PositionError::UnsupportedPlayers(self.num_players));
+        }
+
+        let mut state = GameState::empty(self.geometry);
+        state.num_players = self.num_players;
+        state.ply = self.ply;
+        state.win_rule = self.win_rule;
+
+        if self.current_player >= self.num_players {
+            return Err(PositionError::InvalidPlayer(self.current_player));
+        }
+        state.current_player = self.current_player;
+
+        let mut counts = vec![0; self.num_players as usize];
+        for &((x, y), player) in &self.stones {
+            if !state.is_valid_location(x, y) {
+                return Err(PositionError::InvalidLocation(x, y));
+            }
+            if player >= self.num_players {
+                return Err(PositionError::InvalidPlayer(player));
+            }
+
+            state.set(x, y, Tile::Player(player));
+            counts[player as usize] += 1;
+        }
+
+        for (player, &count) in counts.iter().enumerate() {
+            let player = player as u8;
+            if count == 0 || count > state.home(player).len() {
+                return Err(PositionError::StoneCount(player, count));
+            }
+        }
+
+        if let Some(player) = state.winner() {
+            return Err(PositionError::AlreadyWon(player));
+        }
+
+        Ok(state)
+    }
+}
+
+mod tests {
+    #[test]
+    fn test_builder() {
+        use {GameState, Geometry, Move, Tile, WinRule};
+        use builder::{PositionBuilder, PositionError};
+
+        let start = GameState::new(3);
+        assert_eq!(PositionBuilder::from_state(&start).build(), Ok(start));
+
+        let mut state = GameState::default();
+        state.move_piece(Move { from: (6, 3), to: (6, 5) });
+        assert_eq!(PositionBuilder::from_state(&state).build(), Ok(state));
+
+        let handicap = PositionBuilder::from_state(&GameState::default())
+            .remove(6, 0)
+            .remove(6, 1)
+            .build()
+            .unwrap();
+        assert_eq!(handicap.home(0).iter().filter(|&&(x, y)| handicap.get(x, y) != Tile::Empty).count(), 13);
+
+        let mut builder = PositionBuilder::new(Geometry::Star, 2);
+        builder.place(6, 8, 0).place(6, 9, 1).current_player(1).ply(10).win_rule(WinRule::AllStones);
+        let state = builder.build().unwrap();
+        assert_eq!(state.current_player(), 1);
+        assert_eq!(state.win_rule(), WinRule::AllStones);
+        assert_eq!(state.possible_moves().len(), 6);
+
+        assert_eq!(builder.clone().place(0, 0, 0).build(), Err(PositionError::InvalidLocation(0, 0)));
+        assert_eq!(builder.clone().place(6, 10, 2).build(), Err(PositionError::InvalidPlayer(2)));
+        assert_eq!(builder.clone().current_player(2).build(), Err(PositionError::InvalidPlayer(2)));
+        assert_eq!(builder.clone().remove(6, 9).build(), Err(PositionError::StoneCount(1, 0)));
+        assert_eq!(PositionBuilder::new(Geometry::Square, 3).build(), Err(PositionError::UnsupportedPlayers(3)));
+
+        let mut builder = PositionBuilder::from_state(&GameState::default());
+        for player in 0..2 {
+            for &(x, y) in &GameState::default().home(player) {
+                builder.remove(x, y);
+            }
+        }
+        for player in 0..2 {
+            for &(x, y) in &GameState::default().targets(player) {
+                builder.place(x, y, player);
+            }
+        }
+        assert_eq!(builder.build(), Err(PositionError::AlreadyWon(0)));
+
+        builder.place(0, 4, 0);
+        assert_eq!(builder.build(), Err(PositionError::StoneCount(0, 16)));
+    }
+}
@@ -0,0 +1,238 @@
+//! Game clocks
+//!
+//! A `TimeControl` describes how much time the players get: a base time, optionally renewed
+//! after a number of moves, a Fischer increment added after every move and a Bronstein delay,
+//! i.e. the time a move took is given back after the move, but at most the delay.
+//!
+//! Time controls are written in the style of the PGN `TimeControl` tag, as
+//! `[<moves>/]<base>[+<increment>][d<delay>]` with all times in seconds, e.g. `40/5400+30`,
+//! `300+5` or `180d2`.
+//!
+//! The `Clock` does not measure time itself. The time a player took for a move is passed to it,
+//! which keeps games reproducible and lets the caller decide how to measure it.
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use notation::NotationError;
+
+/// The time the players get for their moves.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimeControl {
+    /// The time every player starts with. It is added again at the start of every period.
+    pub base: Duration,
+    /// The time added after every move.
+    pub increment: Duration,
+    /// The time of a move up to which it is not charged.
+    pub delay: Duration,
+    /// The number of moves of a period. `None` means the whole game is a single period.
+    pub moves_per_period: Option<usize>,
+}
+
+impl TimeControl {
+    /// A time control with only a base time for the whole game.
+    pub fn sudden_death(base: Duration) -> TimeControl {
+        TimeControl {
+            base,
+            increment: Duration::from_secs(0),
+            delay: Duration::from_secs(0),
+            moves_per_period: None,
+        }
+    }
+}
+
+fn format_seconds(duration: Duration) -> String {
+    if duration.subsec_millis() == 0 {
+        duration.as_secs().to_string()
+    } else {
+        format!("{}.{:03}", duration.as_secs(), duration.subsec_millis()).trim_end_matches('0').to_owned()
+    }
+}
+
+fn parse_seconds(s: &str) -> Result<Duration, NotationError> {
+    match s.parse::<f64>() {
+        Ok(seconds) if seconds >= 0.0 && seconds.is_finite() => Ok(Duration::from_millis((seconds * 1000.0).round() as u64)),
+        _ => Err(NotationError::InvalidTimeControl),
+    }
+}
+
+impl fmt::Display for TimeControl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(moves) = self.moves_per_period {
+            write!(f, "{}/", moves)?;
+        }
+        write!(f, "{}", format_seconds(self.base))?;
+        if self.increment > Duration::from_secs(0) {
+            write!(f, "+{}", format_seconds(self.increment))?;
+        }
+        if self.delay > Duration::from_secs(0) {
+            write!(f, "d{}", format_seconds(self.delay))?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for TimeControl {
+    type Err = NotationError;
+
+    fn from_str(s: &str) -> Result<TimeControl, NotationError> {
+        let s = s.trim();
+        let (moves_per_period, rest) = match s.find('/') {
+            Some(slash) => match s[..slash].parse() {
+                Ok(moves) if moves > 0 => (Some(moves), &s[slash+1..]),
+                _ => return Err(NotationError::InvalidTimeControl),
+            },
+            None => (None, s),
+        };
+
+        let (rest, delay) = match rest.find('d') {
+            Some(d) => (&rest[..d], parse_seconds(&rest[d+1..])?),
+            None => (rest, Duration::from_secs(0)),
+        };
+
+        let (base, increment) = match rest.find('+') {
+            Some(plus) => (parse_seconds(&rest[..plus])?, parse_seconds(&rest[plus+1..])?),
+            None => (parse_seconds(rest)?, Duration::from_secs(0)),
+        };
+
+        Ok(TimeControl {
+            base,
+            increment,
+            delay,
+            moves_per_period,
+        })
+    }
+}
+
+/// The remaining time of every player of a game.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Clock {
+    time_control: TimeControl,
+    remaining: Vec<Duration>,
+    /// The number of moves every player made on this clock.
+    moves: Vec<usize>,
+    /// The player who ran out of time, if any.
+    flagged: Option<u8>,
+}
+
+impl Clock {
+    pub fn new(time_control: TimeControl, num_players: u8) -> Clock {
+        Clock {
+            time_control,
+            remaining: vec![time_control.base; num_players as usize],
+            moves: vec![0; num_players as usize],
+            flagged: None,
+        }
+    }
+
+    /// The number of players whose time the clock keeps.
+    pub fn num_players(&self) -> u8 {
+        self.remaining.len() as u8
+    }
+
+    pub fn time_control(&self) -> TimeControl {
+        self.time_control
+    }
+
+    /// The time `player` has left for its remaining moves of the period.
+    pub fn remaining(&self, player: u8) -> Duration {
+        self.remaining[player as usize]
+    }
+
+    /// The time `player` has left after thinking for `elapsed` on its current move, `None` if its
+    /// flag fell.
+    pub fn remaining_after(&self, player: u8, elapsed: Duration) -> Option<Duration> {
+        self.remaining[player as usize].checked_sub(elapsed)
+    }
+
+    /// The number of moves `player` has to make until the time of the next period is added.
+    pub fn moves_to_go(&self, player: u8) -> Option<usize> {
+        self.time_control.moves_per_period.map(|moves| moves - self.moves[player as usize] % moves)
+    }
+
+    /// The player who ran out of time, if any.
+    pub fn flagged(&self) -> Option<u8> {
+        self.flagged
+    }
+
+    /// Charges `player` with the `elapsed` time of a move it made. Returns false if the flag of
+    /// the player fell before the move was made.
+    pub fn record_move(&mut self, player: u8, elapsed: Duration) -> bool {
+        if !self.check_flag(player, elapsed) {
+            return false;
+        }
+
+        let time_control = self.time_control;
+        let remaining = &mut self.remaining[player as usize];
+        *remaining -= elapsed;
+        *remaining += ::std::cmp::min(elapsed, time_control.delay) + time_control.increment;
+
+        self.moves[player as usize] += 1;
+        if let Some(moves) = time_control.moves_per_period {
+            // A new period starts.
+            if self.moves_to_go(player) == Some(moves) {
+                self.remaining[player as usize] += time_control.base;
+            }
+        }
+
+        true
+    }
+
+    /// Checks whether `player` still has time after thinking for `elapsed` on its current move.
+    /// Otherwise its flag falls, which is remembered by the clock.
+    pub fn check_flag(&mut self, player: u8, elapsed: Duration) -> bool {
+        if self.flagged.is_none() && self.remaining_after(player, elapsed).is_none() {
+            self.flagged = Some(player);
+        }
+
+        self.flagged != Some(player)
+    }
+}
+
+mod tests {
+    #[test]
+    fn test_time_control_notation() {
+        use std::time::Duration;
+        use clock::TimeControl;
+        use notation::NotationError;
+
+        let time_control = TimeControl {
+            base: Duration::from_secs(5400),
+            increment: Duration::from_millis(2500),
+            delay: Duration::from_secs(0),
+            moves_per_period: Some(40),
+        };
+        assert_eq!(time_control.to_string(), "40/5400+2.5");
+        assert_eq!("40/5400+2.5".parse(), Ok(time_control));
+        assert_eq!("180d2".parse::<TimeControl>().unwrap().delay, Duration::from_secs(2));
+        assert_eq!("300".parse(), Ok(TimeControl::sudden_death(Duration::from_secs(300))));
+        assert_eq!("0/300".parse::<TimeControl>(), Err(NotationError::InvalidTimeControl));
+        assert_eq!("300+x".parse::<TimeControl>(), Err(NotationError::InvalidTimeControl));
+    }
+
+    #[test]
+    fn test_clock() {
+        use std::time::Duration;
+        use clock::{Clock, TimeControl};
+
+        let mut clock = Clock::new("2/10+1d2".parse().unwrap(), 2);
+        assert!(clock.record_move(0, Duration::from_secs(1)));
+        assert_eq!(clock.remaining(0), Duration::from_secs(11));
+        assert!(clock.record_move(0, Duration::from_secs(5)));
+        assert_eq!(clock.remaining(0), Duration::from_secs(19));
+        assert_eq!(clock.moves_to_go(0), Some(2));
+        assert_eq!(clock.moves_to_go(1), Some(2));
+
+        assert!(clock.check_flag(1, Duration::from_secs(10)));
+        assert!(!clock.check_flag(1, Duration::from_millis(10_001)));
+        assert_eq!(clock.flagged(), Some(1));
+        assert!(!clock.record_move(1, Duration::from_secs(1)));
+        assert_eq!(clock.remaining(1), Duration::from_secs(10));
+
+        let mut clock = Clock::new(TimeControl::sudden_death(Duration::from_secs(1)), 3);
+        assert!(clock.record_move(2, Duration::from_millis(400)));
+        assert_eq!(clock.remaining_after(2, Duration::from_millis(500)), Some(Duration::from_millis(100)));
+        assert_eq!(clock.remaining_after(2, Duration::from_millis(700)), None);
+    }
+}
@@ -0,0 +1,243 @@
+//! ASCII diagrams of positions
+//!
+//! A diagram draws every row of the board containing holes on a line of its own. Holes are two
+//! characters apart and, on star boards, even rows are shifted half a hole to the right, so the
+//! diagram shows the hexagonal grid. Stones are drawn as the player letters `a` to `f`, empty
+//! holes as `.`:
+//!
+//! ```text
+//!              a
+//!             a a
+//!            a a a
+//!             ...
+//!             b b
+//!              b
+//! a to move, ply 0
+//! ```
+//!
+//! The last line holds the player to move, the ply and, for boards other than the standard star
+//! and win rules other than `one-stone`, the board and the win rule as in the position notation
+//! of the `notation` module.
+//!
+//! Optionally, the files are written above and below the board and the ranks to its left and
+//! right. The last move can be highlighted by writing the moved stone in upper case and the hole
+//! it left as `*`.
+
+use std::fmt;
+use std::str::FromStr;
+
+use {BOARD_HEIGHT, MAX_BOARD_WIDTH, GameState, Geometry, Move, Tile, WinRule};
+use notation::{NotationError, parse_options};
+
+/// A diagram of a position with optional coordinates and last move highlighting.
+#[derive(Copy, Clone, Debug)]
+pub struct Diagram<'a> {
+    state: &'a GameState,
+    coordinates: bool,
+    last_move: Option<Move>,
+}
+
+impl<'a> Diagram<'a> {
+    pub fn coordinates(mut self, coordinates: bool) -> Diagram<'a> {
+        self.coordinates = coordinates;
+        self
+    }
+
+    pub fn last_move(mut self, last_move: Option<Move>) -> Diagram<'a> {
+        self.last_move = last_move;
+        self
+    }
+}
+
+/// Returns the rows of the board which contain holes.
+fn rows(state: &GameState) -> Vec<i8> {
+    (0..BOARD_HEIGHT as i8).filter(|&y| (0..MAX_BOARD_WIDTH as i8).any(|x| state.is_valid_location(x, y))).collect()
+}
+
+/// Returns the number of files of the board, i.e. one more than the rightmost file with a hole.
+fn files(state: &GameState) -> i8 {
+    (0..MAX_BOARD_WIDTH as i8).filter(|&x| (0..BOARD_HEIGHT as i8).any(|y| state.is_valid_location(x, y))).max().unwrap_or(0) + 1
+}
+
+/// The line of file letters written above and below the board, aligned to the odd rows.
+fn file_labels(state: &GameState) -> String {
+    (0..files(state)).map(|x| ((b'a' + x as u8) as char).to_string()).collect::<Vec<_>>().join(" ")
+}
+
+impl<'a> fmt::Display for Diagram<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = self.state;
+        let hex = state.geometry() != Geometry::Square;
+        let margin = if self.coordinates { "   " } else { "" };
+
+        if self.coordinates {
+            writeln!(f, "{}{}", margin, file_labels(state))?;
+        }
+
+        for y in rows(state) {
+            let mut line = vec![' '; 2 * files(state) as usize + 1];
+            for x in 0..MAX_BOARD_WIDTH as i8 {
+                let column = 2 * x as usize + if hex && y % 2 == 0 { 1 } else { 0 };
+                let moved = self.last_move.is_some_and(|mov| mov.to == (x, y));
+                let left = self.last_move.is_some_and(|mov| mov.from == (x, y));
+                line[column] = match state.get(x, y) {
+                    Tile::Invalid => continue,
+                    Tile::Empty if left => '*',
+                    Tile::Empty => '.',
+                    Tile::Player(player) if moved => (b'A' + player) as char,
+                    Tile::Player(player) => (b'a' + player) as char,
+                };
+            }
+
+            let line: String = line.into_iter().collect();
+            if self.coordinates {
+                writeln!(f, "{:>2} {} {}", y + 1, line, y + 1)?;
+            } else {
+                writeln!(f, "{}", line.trim_end())?;
+            }
+        }
+
+        if self.coordinates {
+            writeln!(f, "{}{}", margin, file_labels(state))?;
+        }
+
+        write!(f, "{} to move, ply {}", (b'a' + state.current_player()) as char, state.ply)?;
+        if state.geometry() != Geometry::default() {
+            write!(f, ", {}", state.geometry())?;
+        }
+        if state.win_rule() != WinRule::default() {
+            write!(f, ", {}", state.win_rule())?;
+        }
+        Ok(())
+    }
+}
+
+impl GameState {
+    /// Returns a diagram of the position, without coordinates and highlighting by default.
+    pub fn diagram(&self) -> Diagram<'_> {
+        Diagram {
+            state: self,
+            coordinates: false,
+            last_move: None,
+        }
+    }
+}
+
+impl fmt::Display for GameState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.diagram().fmt(f)
+    }
+}
+
+impl FromStr for GameState {
+    type Err = NotationError;
+
+    /// Parses a diagram with or without coordinates and highlighting.
+    fn from_str(s: &str) -> Result<GameState, NotationError> {
+        let mut lines: Vec<_> = s.lines().map(str::trim).filter(|line| !line.is_empty()).collect();
+        let footer = lines.pop().ok_or(NotationError::MissingField)?;
+
+        let fields: Vec<_> = footer.split(", ").collect();
+        let side = match fields.first().map(|side| side.split_whitespace().collect::<Vec<_>>()) {
+            Some(ref words) if words.len() == 3 && words[1] == "to" && words[2] == "move" => words[0].to_owned(),
+            _ => return Err(NotationError::MissingField),
+        };
+        let ply = match fields.get(1) {
+            Some(ply) if ply.starts_with("ply ") => ply["ply ".len()..].to_owned(),
+            _ => return Err(NotationError::MissingField),
+        };
+        if fields.len() > 4 {
+            return Err(NotationError::MissingField);
+        }
+        let (geometry, win_rule) = parse_options(&fields[2..])?;
+
+        let labels = file_labels(&GameState::empty(geometry));
+        let mut placement = Vec::new();
+        for line in lines {
+            let mut tokens: Vec<_> = line.split_whitespace().collect();
+            if tokens.join(" ") == labels {
+                continue;
+            }
+
+            // Strip the ranks.
+            while tokens.first().is_some_and(|token| token.parse::<u8>().is_ok()) {
+                tokens.remove(0);
+            }
+            while tokens.last().is_some_and(|token| token.parse::<u8>().is_ok()) {
+                tokens.pop();
+            }
+
+            let mut row = String::new();
+            let mut empty = 0;
+            for token in tokens {
+                let mut chars = token.chars();
+                let c = match (chars.next(), chars.next()) {
+                    (Some(c), None) => c,
+                    _ => return Err(NotationError::InvalidPlacement),
+                };
+
+                match c {
+                    '.' | '*' => empty += 1,
+                    'a'..='z' | 'A'..='Z' => {
+                        if empty > 0 {
+                            row.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        row.push(c.to_ascii_lowercase());
+                    }
+                    _ => return Err(NotationError::InvalidPlacement),
+                }
+            }
+
+            if empty > 0 {
+                row.push_str(&empty.to_string());
+            }
+            placement.push(row);
+        }
+
+        GameState::from_notation(&format!("{} {} {} {} {}", placement.join("/"), side, ply, geometry, win_rule))
+    }
+}
+
+mod tests {
+    #[test]
+    fn test_diagram() {
+        use {GameState, Geometry, Move, WinRule};
+        use notation::NotationError;
+
+        let mut state = GameState::default();
+        let text = state.to_string();
+        assert!(text.starts_with("             a\n            a a\n"));
+        assert!(text.ends_with("             b\na to move, ply 0"));
+        assert_eq!(text.parse(), Ok(state));
+
+        let mov = Move { from: (6, 3), to: (6, 5) };
+        state.move_piece(mov);
+        let text = state.diagram().coordinates(true).last_move(Some(mov)).to_string();
+        assert!(text.starts_with("   a b c d e f g h i j k l m\n 1              a              1\n"));
+        assert!(text.contains("\n 4           a * a a           4\n"));
+        assert!(text.contains("\n 6   . . . . . A . . . . . .   6\n"));
+        assert_eq!(text.parse(), Ok(state));
+
+        for &geometry in &[Geometry::SmallStar { size: 3 }, Geometry::Square] {
+            for &num_players in &[2, 4] {
+                let state = GameState::with_geometry(geometry, num_players);
+                assert_eq!(state.to_string().parse(), Ok(state));
+                assert_eq!(state.diagram().coordinates(true).to_string().parse(), Ok(state));
+            }
+        }
+
+        for &win_rule in &[WinRule::AllStones, WinRule::NoParking { ply: 60 }] {
+            for &geometry in &[Geometry::Star, Geometry::Square] {
+                let mut state = GameState::with_geometry(geometry, 2);
+                state.set_win_rule(win_rule);
+                assert!(state.to_string().ends_with(&format!(", {}", win_rule)));
+                assert_eq!(state.to_string().parse(), Ok(state));
+            }
+        }
+
+        assert_eq!("a\na to move".parse::<GameState>(), Err(NotationError::MissingField));
+        assert_eq!("a\na to move, ply 0".parse::<GameState>(), Err(NotationError::InvalidPlacement));
+        assert_eq!(" a\na a\nx to move, ply 0".parse::<GameState>(), Err(NotationError::InvalidPlacement));
+    }
+}
@@ -0,0 +1,284 @@
+//! Board geometries
+//!
+//! Every board is stored in the same `MAX_BOARD_WIDTH` x `BOARD_HEIGHT` array of tiles, holes
+//! which do not belong to a board are `Tile::Invalid`.
+//!
+//! Star boards use offset coordinates on a hexagonal grid: odd rows are shifted half a hole to
+//! the left, so the neighbours of `(x, y)` in the adjacent rows are `x` and `x+1` for even `y`
+//! and `x-1` and `x` for odd `y`. All stars are centered on `(6, 8)`, so smaller stars are
+//! subsets of the standard 121-hole star. The square board uses the upper left 16x16 holes of
+//! the array, every hole being adjacent to its eight surrounding holes.
+
+use std::convert::TryFrom;
+
+use {BOARD_HEIGHT, MAX_BOARD_WIDTH};
+
+/// The shape of the board and the camps the players start in.
+///
+/// Only stars with points of 1 to 4 rows fit into the board, see `is_valid`. Deserializing any
+/// other size fails.
+#[derive(Copy, Clone, Debug, Default, Hash, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "SerializedGeometry")]
+pub enum Geometry {
+    /// The 121-hole star of Chinese checkers. Two players start with 15 stones each, the camps
+    /// at the top and bottom extending one row into the center. Three, four or six players
+    /// start with the 10 stones of a point of the star.
+    #[default]
+    Star,
+    /// A star whose points have `size` rows, each player starting with the stones of one
+    /// point. A size of 4 gives the standard star with 10 stones also for two players, a size
+    /// of 3 a beginner's star of 73 holes with 6 stones per player.
+    SmallStar { size: u8 },
+    /// The 16x16 board of the original Halma. Two players start with 19 stones in opposite
+    /// corners, four players with 13 stones in every corner.
+    Square,
+}
+
+const SQUARE_SIZE: i8 = 16;
+
+/// The number of rows of a point of the largest star fitting into the board.
+const MAX_STAR_SIZE: u8 = 4;
+
+/// The serialized form of a `Geometry`, which is checked by converting it.
+#[derive(Deserialize)]
+enum SerializedGeometry {
+    Star,
+    SmallStar { size: u8 },
+    Square,
+}
+
+impl TryFrom<SerializedGeometry> for Geometry {
+    type Error = String;
+
+    fn try_from(geometry: SerializedGeometry) -> Result<Geometry, String> {
+        let geometry = match geometry {
+            SerializedGeometry::Star => Geometry::Star,
+            SerializedGeometry::SmallStar { size } => Geometry::SmallStar { size },
+            SerializedGeometry::Square => Geometry::Square,
+        };
+
+        if geometry.is_valid() {
+            Ok(geometry)
+        } else {
+            Err(format!("invalid board: {:?}", geometry))
+        }
+    }
+}
+
+impl Geometry {
+    /// Whether the board fits into the `MAX_BOARD_WIDTH` x `BOARD_HEIGHT` holes and has holes
+    /// for the stones, i.e. it is no star with points of 0 or more than 4 rows.
+    pub fn is_valid(&self) -> bool {
+        match *self {
+            Geometry::SmallStar { size } => (1..=MAX_STAR_SIZE).contains(&size),
+            Geometry::Star | Geometry::Square => true,
+        }
+    }
+
+    /// The numbers of players this board can be played with, none if it is not valid.
+    pub fn player_counts(&self) -> &'static [u8] {
+        match *self {
+            _ if !self.is_valid() => &[],
+            Geometry::Star | Geometry::SmallStar { .. } => &[2, 3, 4, 6],
+            Geometry::Square => &[2, 4],
+        }
+    }
+
+    /// The number of rows of a point of the star, `None` for non-star boards.
+    fn star_size(&self) -> Option<i8> {
+        match *self {
+            Geometry::Star => Some(4),
+            Geometry::SmallStar { size } => Some(size as i8),
+            Geometry::Square => None,
+        }
+    }
+
+    pub fn is_valid_location(&self, x: i8, y: i8) -> bool {
+        if x < 0 || x >= MAX_BOARD_WIDTH as i8 || y < 0 || y >= BOARD_HEIGHT as i8 {
+            return false;
+        }
+
+        match self.star_size() {
+            Some(n) => {
+                // The star is the union of a triangle pointing up and one pointing down.
+                let (q, r, s) = cube(x, y);
+                (q <= n && r <= n && s <= n) || (q >= -n && r >= -n && s >= -n)
+            }
+            None => x < SQUARE_SIZE && y < SQUARE_SIZE,
+        }
+    }
+
+    /// Returns the directions a stone on `(x, y)` can move in as pairs of the offset of the
+    /// adjacent hole and the offset of the hole behind it, which is reached by a jump.
+    pub fn directions(&self, y: i8) -> Vec<(i8, i8, i8, i8)> {
+        match *self {
+            Geometry::Star | Geometry::SmallStar { .. } => {
+                vec![(-1, 0, -2, 0), (1, 0, 2, 0), (-y%2+1, 1, 1, 2), (-y%2, 1, -1, 2), (-y%2+1, -1, 1, -2), (-y%2, -1, -1, -2)]
+            }
+            Geometry::Square => {
+                let mut directions = Vec::with_capacity(8);
+                for dy in -1..2 {
+                    for dx in -1..2 {
+                        if dx != 0 || dy != 0 {
+                            directions.push((dx, dy, 2*dx, 2*dy));
+                        }
+                    }
+                }
+                directions
+            }
+        }
+    }
+
+    /// Returns the corners the players start in, in turn order.
+    fn seats(&self, num_players: u8) -> &'static [usize] {
+        match (*self, num_players) {
+            (Geometry::Square, 2) => &[0, 2],
+            (Geometry::Square, 4) => &[0, 1, 2, 3],
+            (Geometry::Square, _) => panic!("Unsupported number of players: {}", num_players),
+            (_, 2) => &[0, 3],
+            (_, 3) => &[0, 2, 4],
+            (_, 4) => &[0, 1, 3, 4],
+            (_, 6) => &[0, 1, 2, 3, 4, 5],
+            _ => panic!("Unsupported number of players: {}", num_players),
+        }
+    }
+
+    fn opposite(&self, corner: usize) -> usize {
+        match *self {
+            Geometry::Square => (corner + 2) % 4,
+            _ => (corner + 3) % 6,
+        }
+    }
+
+    /// The holes `player` starts the game in.
+    pub fn home(&self, num_players: u8, player: u8) -> Vec<(i8, i8)> {
+        self.camp(num_players, self.seats(num_players)[player as usize])
+    }
+
+    /// The holes `player` has to fill to win the game, i.e. the camp opposite of its home.
+    pub fn targets(&self, num_players: u8, player: u8) -> Vec<(i8, i8)> {
+        self.camp(num_players, self.opposite(self.seats(num_players)[player as usize]))
+    }
+
+    /// Returns the holes of a corner of the board, clockwise starting at the top (for stars) or
+    /// the top left (for the square board).
+    fn camp(&self, num_players: u8, corner: usize) -> Vec<(i8, i8)> {
+        let mut result = Vec::new();
+        for y in 0..BOARD_HEIGHT as i8 {
+            for x in 0..MAX_BOARD_WIDTH as i8 {
+                if self.is_valid_location(x, y) && self.in_camp(num_players, corner, x, y) {
+                    result.push((x, y));
+                }
+            }
+        }
+
+        result
+    }
+
+    fn in_camp(&self, num_players: u8, corner: usize, x: i8, y: i8) -> bool {
+        match *self {
+            Geometry::Square => {
+                // Mirror the board so that the corner is the upper left one.
+                let x = if corner == 1 || corner == 2 { SQUARE_SIZE-1-x } else { x };
+                let y = if corner == 2 || corner == 3 { SQUARE_SIZE-1-y } else { y };
+                let k = if num_players == 2 { 5 } else { 4 };
+                x + y <= k && x < k && y < k
+            }
+            _ => {
+                let n = self.star_size().unwrap();
+                let (q, r, s) = cube(x, y);
+
+                // Two players on the standard star also get the middle five holes of the row
+                // of the center next to their point.
+                if *self == Geometry::Star && num_players == 2 {
+                    return if corner == 0 {
+                        r < -n || (r == -n && q >= 0 && s >= 0)
+                    } else {
+                        r > n || (r == n && q <= 0 && s <= 0)
+                    };
+                }
+
+                match corner {
+                    0 => r < -n,
+                    1 => q > n,
+                    2 => s < -n,
+                    3 => r > n,
+                    4 => q < -n,
+                    _ => s > n,
+                }
+            }
+        }
+    }
+}
+
+/// Converts offset coordinates of a star board into cube coordinates relative to its center.
+fn cube(x: i8, y: i8) -> (i8, i8, i8) {
+    let dx = 2*x - y%2 - 12;
+    let dy = y - 8;
+    let q = (dx - dy) / 2;
+    (q, dy, -q-dy)
+}
+
+mod tests {
+    #[test]
+    fn test_star() {
+        use geometry::Geometry;
+
+        // The bounds of the rows of the standard star.
+        let rows = [
+            (6, 6), (6, 7), (5, 7), (5, 8), (0, 12), (1, 12), (1, 11), (2, 11), (2, 10), (2, 11), (1, 11), (1, 12), (0, 12), (5, 8), (5, 7), (6, 7), (6, 6)
+        ];
+        for (y, &(left, right)) in rows.iter().enumerate() {
+            for x in 0..16 {
+                assert_eq!(Geometry::Star.is_valid_location(x, y as i8), x >= left && x <= right);
+            }
+        }
+
+        for &(size, holes, stones) in &[(2, 37, 3), (3, 73, 6), (4, 121, 10)] {
+            let geometry = Geometry::SmallStar { size };
+            let valid = (0..16).flat_map(|x| (0..17).map(move |y| (x, y)))
+                .filter(|&(x, y)| geometry.is_valid_location(x, y))
+                .count();
+            assert_eq!(valid, holes);
+
+            for player in 0..6 {
+                assert_eq!(geometry.home(6, player).len(), stones);
+            }
+        }
+
+        assert_eq!(Geometry::Star.home(2, 0).len(), 15);
+        assert_eq!(Geometry::Star.targets(2, 0), Geometry::Star.home(2, 1));
+        assert_eq!(Geometry::Star.home(6, 1), vec![(9, 4), (10, 4), (11, 4), (12, 4), (10, 5), (11, 5), (12, 5), (10, 6), (11, 6), (11, 7)]);
+    }
+
+    #[test]
+    fn test_invalid_size() {
+        use GameState;
+        use geometry::Geometry;
+
+        for &size in &[0, 5, 7] {
+            let geometry = Geometry::SmallStar { size };
+            assert!(!geometry.is_valid());
+            assert!(geometry.player_counts().is_empty());
+
+            let json = ::serde_json::to_string(&geometry).unwrap();
+            assert!(::serde_json::from_str::<Geometry>(&json).is_err());
+        }
+
+        let json = ::serde_json::to_string(&GameState::with_geometry(Geometry::SmallStar { size: 2 }, 2)).unwrap();
+        assert!(::serde_json::from_str::<GameState>(&json).is_ok());
+        assert!(::serde_json::from_str::<GameState>(&json.replace("\"size\":2", "\"size\":9")).is_err());
+        assert_eq!(::serde_json::from_str::<Geometry>("\"Square\"").unwrap(), Geometry::Square);
+    }
+
+    #[test]
+    fn test_square() {
+        use geometry::Geometry;
+
+        assert_eq!(Geometry::Square.home(2, 0).len(), 19);
+        assert_eq!(Geometry::Square.home(4, 3).len(), 13);
+        assert!(Geometry::Square.home(2, 1).contains(&(15, 15)));
+        assert_eq!(Geometry::Square.targets(4, 1), Geometry::Square.home(4, 3));
+        assert_eq!(Geometry::Square.directions(3).len(), 8);
+    }
+}
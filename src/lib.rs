@@ -5,6 +5,22 @@ extern crate serde;
 #[macro_use] extern crate serde_derive;
 
 pub mod ai;
+pub mod builder;
+pub mod clock;
+pub mod diagram;
+pub mod geometry;
+pub mod notation;
+pub mod record;
+
+use std::collections::{HashMap, VecDeque};
+use std::convert::TryFrom;
+use std::error;
+use std::fmt;
+use std::time::Duration;
+
+use clock::Clock;
+
+pub use geometry::Geometry;
 
 #[derive(Copy, Clone, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Tile {
@@ -13,14 +29,49 @@ pub enum Tile {
     Player(u8),
 }
 
+/// The width of the standard star. Boards of other geometries may be wider, every board fits
+/// into `MAX_BOARD_WIDTH` x `BOARD_HEIGHT` holes.
 pub const BOARD_WIDTH: u8 = 13;
+pub const MAX_BOARD_WIDTH: u8 = 16;
 pub const BOARD_HEIGHT: u8 = 17;
 
+fn default_num_players() -> u8 {
+    2
+}
+
+/// The condition under which a player has won the game.
+///
+/// All rules have to deal with spoiling, i.e. a player leaving stones in its home to block the
+/// target area of the opposing player.
+#[derive(Copy, Clone, Debug, Default, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WinRule {
+    /// The target area is completely occupied and at least one of the stones in it is the
+    /// player's own, so stones of other players left in it can not prevent a win.
+    #[default]
+    OneStone,
+    /// Every hole of the target area is occupied by one of the player's own stones.
+    AllStones,
+    /// Like `OneStone`, but a player who still has stones in its home after `ply` plies is
+    /// disqualified. Once all other players are disqualified, the remaining one wins. If all
+    /// players are disqualified at once, the game is drawn.
+    NoParking { ply: usize },
+    /// Like `OneStone`, but once `ply` plies were played the game ends and the player with the
+    /// most stones in its target area wins. If there is no such player, the game is drawn.
+    PartialFill { ply: usize },
+}
+
 #[derive(Copy, Clone, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
 pub struct GameState {
-    board: [[Tile; BOARD_HEIGHT as usize]; BOARD_WIDTH as usize],
+    board: [[Tile; BOARD_HEIGHT as usize]; MAX_BOARD_WIDTH as usize],
+    /// The number of moves played since the start of the game.
     ply: usize,
     current_player: u8,
+    #[serde(default = "default_num_players")]
+    num_players: u8,
+    #[serde(default)]
+    win_rule: WinRule,
+    #[serde(default)]
+    geometry: Geometry,
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
@@ -38,23 +89,193 @@ impl Move {
     }
 }
 
-#[derive(Clone, Debug, Default, PartialEq, Eq)]
+/// The reason a move was rejected by `Game::try_move`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MoveError {
+    /// The origin or the destination of the move is not a hole of the board.
+    OffBoard,
+    /// There is no stone on the origin of the move.
+    EmptyOrigin,
+    /// The stone on the origin belongs to a player who is not to move.
+    WrongPlayer,
+    /// The destination can not be reached from the origin with a single step or jump sequence.
+    Unreachable,
+    /// The game is already over.
+    GameOver,
+    /// The player ran out of time before making the move.
+    FlagFall,
+}
+
+impl fmt::Display for MoveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MoveError::OffBoard => write!(f, "move leaves the board"),
+            MoveError::EmptyOrigin => write!(f, "no stone on the origin square"),
+            MoveError::WrongPlayer => write!(f, "stone belongs to a player who is not to move"),
+            MoveError::Unreachable => write!(f, "destination is not reachable from the origin"),
+            MoveError::GameOver => write!(f, "the game is already over"),
+            MoveError::FlagFall => write!(f, "the player ran out of time"),
+        }
+    }
+}
+
+impl error::Error for MoveError {}
+
+/// The conditions under which a `Game` ends in a draw.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DrawRules {
+    /// The game is drawn once the same position with the same player to move occurred this
+    /// often. `None` disables draws by repetition.
+    pub repetitions: Option<usize>,
+    /// The game is drawn once its ply reaches this number without a winner. `None` disables the
+    /// limit.
+    pub max_plies: Option<usize>,
+}
+
+impl Default for DrawRules {
+    fn default() -> Self {
+        DrawRules {
+            repetitions: Some(3),
+            max_plies: None,
+        }
+    }
+}
+
+/// Why a game is over.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Termination {
+    /// The player filled its target area.
+    Won(u8),
+    /// The current position occurred `DrawRules::repetitions` times.
+    Repetition,
+    /// `DrawRules::max_plies` moves were played, or the move limit of `WinRule::PartialFill` was
+    /// reached without a winner.
+    MoveLimit,
+    /// The player ran out of time and lost.
+    FlagFall(u8),
+}
+
+/// The result of a finished game from the point of view of one player.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Outcome {
+    Win,
+    Loss,
+    Draw,
+}
+
+impl Termination {
+    pub fn outcome(&self, player: u8) -> Outcome {
+        match *self {
+            Termination::Won(winner) if winner == player => Outcome::Win,
+            Termination::Won(_) => Outcome::Loss,
+            Termination::Repetition | Termination::MoveLimit => Outcome::Draw,
+            Termination::FlagFall(loser) if loser == player => Outcome::Loss,
+            Termination::FlagFall(_) => Outcome::Win,
+        }
+    }
+}
+
+/// A position in the move tree of a `Game`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct HistoryNode {
+    /// The move leading to this position. Only the root, i.e. the start position, has none.
+    mov: Option<Move>,
+    parent: Option<usize>,
+    /// The continuations played from this position. The first one is the main line.
+    children: Vec<usize>,
+    /// The index into `children` of the continuation `Game::redo` follows.
+    selected: usize,
+}
+
+/// A game and its history.
+///
+/// Every move ever played is kept in a tree of variations. Taking back moves with `undo` only
+/// moves a cursor through this tree, so they can be replayed with `redo`. Playing a different
+/// move from an earlier position starts a new variation, playing a move which was already played
+/// from that position follows the existing one.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "SerializedGame")]
 pub struct Game {
+    start: GameState,
     state: GameState,
+    /// The moves leading from the start position to the current position.
     moves: Vec<Move>,
+    nodes: Vec<HistoryNode>,
+    current: usize,
+    #[serde(default)]
+    draw_rules: DrawRules,
+    #[serde(default)]
+    clock: Option<Clock>,
+    /// How often each position of `moves` occurred, keyed by `GameState::position`.
+    #[serde(skip)]
+    positions: HashMap<GameState, usize>,
 }
 
-impl GameState {
-    fn is_valid_location(&self, x: i8, y: i8) -> bool {
-        if y < 0 || y >= BOARD_HEIGHT as i8 {
-            return false;
+/// The serialized fields of a `Game`. They are checked by replaying the moves, which also
+/// rebuilds the position counts.
+#[derive(Deserialize)]
+struct SerializedGame {
+    start: GameState,
+    state: GameState,
+    moves: Vec<Move>,
+    nodes: Vec<HistoryNode>,
+    current: usize,
+    #[serde(default)]
+    draw_rules: DrawRules,
+    #[serde(default)]
+    clock: Option<Clock>,
+}
+
+impl TryFrom<SerializedGame> for Game {
+    type Error = String;
+
+    fn try_from(serialized: SerializedGame) -> Result<Game, String> {
+        // Replay without draw rules, since they may have been changed during the game.
+        let mut game = Game::from_state(serialized.start);
+        game.set_draw_rules(DrawRules { repetitions: None, max_plies: None });
+        for (index, &mov) in serialized.moves.iter().enumerate() {
+            game.try_move(mov).map_err(|err| format!("illegal move {}: {}", index + 1, err))?;
+        }
+        if game.state != serialized.state {
+            return Err("the moves do not lead to the current position".to_owned());
         }
 
-        let (left, right) = [
-            (6, 6), (6, 7), (5, 7), (5, 8), (0, 12), (1, 12), (1, 11), (2, 11), (2, 10), (2, 11), (1, 11), (1, 12), (0, 12), (5, 8), (5, 7), (6, 7), (6, 6)
-        ][y as usize];
+        // The variation tree has to lead from its root to the current position by the moves.
+        let nodes = &serialized.nodes;
+        if !nodes.iter().all(|node| node.parent.iter().chain(&node.children).all(|&index| index < nodes.len())) {
+            return Err("invalid variation tree".to_owned());
+        }
+        let mut line = Vec::new();
+        let mut node = Some(serialized.current);
+        while let Some(index) = node {
+            if index >= nodes.len() || line.len() > serialized.moves.len() {
+                return Err("invalid variation tree".to_owned());
+            }
+            line.extend(nodes[index].mov);
+            node = nodes[index].parent;
+        }
+        line.reverse();
+        if line != serialized.moves {
+            return Err("the variation tree does not match the moves".to_owned());
+        }
+
+        if serialized.clock.as_ref().is_some_and(|clock| clock.num_players() != game.state.num_players) {
+            return Err("the clock does not match the number of players".to_owned());
+        }
 
-        x >= left && x <= right
+        Ok(Game {
+            nodes: serialized.nodes,
+            current: serialized.current,
+            draw_rules: serialized.draw_rules,
+            clock: serialized.clock,
+            ..game
+        })
+    }
+}
+
+impl GameState {
+    fn is_valid_location(&self, x: i8, y: i8) -> bool {
+        self.geometry.is_valid_location(x, y)
     }
 
     fn set(&mut self, x: i8, y: i8, tile: Tile) {
@@ -63,37 +284,112 @@ impl GameState {
     }
 
     pub fn get(&self, x: i8, y: i8) -> Tile {
-        assert!(x >= 0 && (x as u8) < BOARD_WIDTH);
+        assert!(x >= 0 && (x as u8) < MAX_BOARD_WIDTH);
         assert!(y >= 0 && (y as u8) < BOARD_HEIGHT);
 
         self.board[x as usize][y as usize]
     }
 
-    pub fn targets(player: u8) -> &'static [(i8, i8)] {
-        if player == 0 {
-            &[
-                (4, 12), (5, 12), (6, 12), (7, 12), (8, 12),
-                (5, 13), (6, 13), (7, 13), (8, 13),
-                (5, 14), (6, 14), (7, 14),
-                (6, 15), (7, 15),
-                (6, 16),
-            ]
-        } else if player == 1 {
-            &[
-                (6, 0),
-                (6, 1), (7, 1),
-                (5, 2), (6, 2), (7, 2),
-                (5, 3), (6, 3), (7, 3), (8, 3),
-                (4, 4), (5, 4), (6, 4), (7, 4), (8, 4),
-            ]
-        } else {
-            unreachable!()
+    /// Creates the starting position for a game of `num_players` players on the standard star.
+    ///
+    /// Two, three, four or six players are supported. Two players start with 15 stones each in
+    /// the top and bottom camps, all other player counts use the 10 holes of a point of the star.
+    pub fn new(num_players: u8) -> Self {
+        GameState::with_geometry(Geometry::Star, num_players)
+    }
+
+    /// Creates the starting position for a game of `num_players` players on the board
+    /// `geometry`.
+    pub fn with_geometry(geometry: Geometry, num_players: u8) -> Self {
+        assert!(geometry.is_valid(), "Invalid board: {:?}", geometry);
+        assert!(geometry.player_counts().contains(&num_players), "Unsupported number of players: {}", num_players);
+
+        let mut state = GameState::empty(geometry);
+        state.num_players = num_players;
+        for player in 0..num_players {
+            for &(x, y) in &state.home(player) {
+                state.set(x, y, Tile::Player(player));
+            }
         }
+
+        state
+    }
+
+    /// Creates a board of the given geometry without any stones.
+    fn empty(geometry: Geometry) -> Self {
+        let mut state = GameState {
+            board: [[Tile::Invalid; BOARD_HEIGHT as usize]; MAX_BOARD_WIDTH as usize],
+            ply: 0,
+            current_player: 0,
+            num_players: 2,
+            win_rule: WinRule::default(),
+            geometry,
+        };
+
+        for y in 0..BOARD_HEIGHT as i8 {
+            for x in 0..MAX_BOARD_WIDTH as i8 {
+                if state.is_valid_location(x, y) {
+                    state.set(x, y, Tile::Empty);
+                }
+            }
+        }
+
+        state
+    }
+
+    pub fn geometry(&self) -> Geometry {
+        self.geometry
+    }
+
+    /// The holes `player` starts the game in.
+    pub fn home(&self, player: u8) -> Vec<(i8, i8)> {
+        self.geometry.home(self.num_players, player)
+    }
+
+    /// The holes `player` has to fill to win the game, i.e. the camp opposite of its home.
+    pub fn targets(&self, player: u8) -> Vec<(i8, i8)> {
+        self.geometry.targets(self.num_players, player)
+    }
+
+    pub fn win_rule(&self) -> WinRule {
+        self.win_rule
+    }
+
+    pub fn set_win_rule(&mut self, win_rule: WinRule) {
+        self.win_rule = win_rule;
     }
 
     pub fn won(&self, player: u8) -> bool {
+        match self.win_rule {
+            WinRule::OneStone => self.filled(player),
+            WinRule::AllStones => self.stones_in_target(player) == self.targets(player).len(),
+            WinRule::NoParking { ply } => {
+                self.filled(player) || (self.ply >= ply && !self.parked(player)
+                    && self.opponents(player).all(|opponent| self.parked(opponent)))
+            }
+            WinRule::PartialFill { ply } => {
+                self.filled(player) || (self.ply >= ply
+                    && self.opponents(player).all(|opponent| self.stones_in_target(opponent) < self.stones_in_target(player)))
+            }
+        }
+    }
+
+    /// Whether the game ended without a winner because of the win rule.
+    pub fn drawn(&self) -> bool {
+        match self.win_rule {
+            WinRule::NoParking { ply } => {
+                self.ply >= ply && (0..self.num_players).all(|player| self.parked(player)) && self.winner().is_none()
+            }
+            WinRule::PartialFill { ply } => self.ply >= ply && self.winner().is_none(),
+            _ => false,
+        }
+    }
+
+    /// Whether the target area of `player` is completely occupied, with at least one of its own
+    /// stones.
+    fn filled(&self, player: u8) -> bool {
         let mut one_stone = false;
-        for &(x, y) in GameState::targets(player) {
+        for &(x, y) in &self.targets(player) {
             if self.get(x, y) == Tile::Empty {
                 return false;
             }
@@ -106,46 +402,160 @@ impl GameState {
         one_stone
     }
 
-    pub fn moves_from(&self, x: i8, y: i8) -> Vec<Move> {
-        let mut result = Vec::with_capacity(128);
-        let mut jumping_targets = Vec::with_capacity(128);
-        jumping_targets.push((x, y));
+    fn stones_in_target(&self, player: u8) -> usize {
+        self.targets(player).iter().filter(|&&(x, y)| self.get(x, y) == Tile::Player(player)).count()
+    }
 
-        while let Some((sx, sy)) = jumping_targets.pop() {
-            for &(dx, dy, jx, jy) in &[(-1, 0, -2, 0), (1, 0, 2, 0), (-y%2+1, 1, 1, 2), (-y%2, 1, -1, 2), (-y%2+1, -1, 1, -2), (-y%2, -1, -1, -2)] {
-                if !self.is_valid_location(sx+jx, sy+jy) {
-                    continue;
-                }
+    /// Whether `player` still has stones in its home.
+    fn parked(&self, player: u8) -> bool {
+        self.home(player).iter().any(|&(x, y)| self.get(x, y) == Tile::Player(player))
+    }
+
+    fn opponents(&self, player: u8) -> impl Iterator<Item=u8> {
+        (0..self.num_players).filter(move |&opponent| opponent != player)
+    }
 
-                if let Tile::Player(_) = self.get(sx+dx, sy+dy) {
-                    if self.get(sx+jx, sy+jy) == Tile::Empty
-                        && !jumping_targets.contains(&(sx+jx, sy+jy))
-                        && !result.contains(&Move { from: (x, y), to: (sx+jx, sy+jy)}) {
-                        jumping_targets.push((sx+jx, sy+jy));
-                        result.push(Move { from: (x, y), to: (sx+jx, sy+jy) });
-                    }
+    /// Returns the player who has won the game, if any.
+    pub fn winner(&self) -> Option<u8> {
+        (0..self.num_players).find(|&player| self.won(player))
+    }
+
+    /// The state without its ply, so states with the same stones on the board and the same
+    /// player to move are equal.
+    fn position(&self) -> GameState {
+        GameState { ply: 0, ..*self }
+    }
+
+    /// Returns the holes reachable from `(x, y)` by jumping over a single stone.
+    fn jumps_from(&self, x: i8, y: i8) -> Vec<(i8, i8)> {
+        let mut result = Vec::with_capacity(8);
+        for (dx, dy, jx, jy) in self.geometry.directions(y) {
+            if !self.is_valid_location(x+jx, y+jy) {
+                continue;
+            }
+
+            if let Tile::Player(_) = self.get(x+dx, y+dy) {
+                if self.get(x+jx, y+jy) == Tile::Empty {
+                    result.push((x+jx, y+jy));
                 }
             }
         }
 
-        for &(dx, dy) in &[(-1, 0), (1, 0), (-y%2+1, 1), (-y%2, 1), (-y%2+1, -1), (-y%2, -1)] {
+        result
+    }
+
+    /// Returns the empty holes adjacent to `(x, y)`.
+    fn steps_from(&self, x: i8, y: i8) -> Vec<(i8, i8)> {
+        let mut result = Vec::with_capacity(8);
+        for (dx, dy, _, _) in self.geometry.directions(y) {
             if !self.is_valid_location(x+dx, y+dy) {
                 continue;
             }
 
             if self.get(x+dx, y+dy) == Tile::Empty {
-                result.push(Move { from: (x, y), to: (x+dx, y+dy) });
+                result.push((x+dx, y+dy));
+            }
+        }
+
+        result
+    }
+
+    pub fn moves_from(&self, x: i8, y: i8) -> Vec<Move> {
+        let mut result = Vec::with_capacity(128);
+        let mut jumping_targets = Vec::with_capacity(128);
+        jumping_targets.push((x, y));
+
+        while let Some((sx, sy)) = jumping_targets.pop() {
+            for to in self.jumps_from(sx, sy) {
+                if !jumping_targets.contains(&to) && !result.contains(&Move { from: (x, y), to }) {
+                    jumping_targets.push(to);
+                    result.push(Move { from: (x, y), to });
+                }
             }
         }
 
+        result.extend(self.steps_from(x, y).into_iter().map(|to| Move { from: (x, y), to }));
+
         assert!(result.iter().all(|&Move { from, to }| from != to));
         result
     }
 
+    /// Reconstructs the holes the stone passes through when `mov` is played.
+    ///
+    /// The returned path starts with `mov.from` and ends with `mov.to`. A step yields a path of
+    /// two holes, a jump sequence one hole per hop plus the origin. If several jump sequences
+    /// lead to `mov.to`, one with the fewest hops is returned. Returns `None` if `mov` is not a
+    /// move of the stone on `mov.from`.
+    pub fn path(&self, mov: Move) -> Option<Vec<(i8, i8)>> {
+        let (fx, fy) = mov.from;
+        let (tx, ty) = mov.to;
+
+        if !self.is_valid_location(fx, fy) || !self.is_valid_location(tx, ty) {
+            return None;
+        }
+
+        if let Tile::Player(_) = self.get(fx, fy) {} else {
+            return None;
+        }
+
+        if self.steps_from(fx, fy).contains(&mov.to) {
+            return Some(vec![mov.from, mov.to]);
+        }
+
+        // Breadth first search over single jumps, remembering where we reached each hole from.
+        let mut parents: Vec<((i8, i8), (i8, i8))> = Vec::new();
+        let mut queue = VecDeque::new();
+        queue.push_back(mov.from);
+
+        while let Some((x, y)) = queue.pop_front() {
+            if (x, y) == mov.to {
+                let mut path = vec![mov.to];
+                let mut hole = mov.to;
+                while hole != mov.from {
+                    hole = parents.iter().find(|&&(to, _)| to == hole).unwrap().1;
+                    path.push(hole);
+                }
+                path.reverse();
+                return Some(path);
+            }
+
+            for to in self.jumps_from(x, y) {
+                if !parents.iter().any(|&(reached, _)| reached == to) {
+                    parents.push((to, (x, y)));
+                    queue.push_back(to);
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Checks whether `mov` is a legal move for the current player.
+    pub fn check_move(&self, mov: Move) -> Result<(), MoveError> {
+        let (fx, fy) = mov.from;
+        let (tx, ty) = mov.to;
+
+        if !self.is_valid_location(fx, fy) || !self.is_valid_location(tx, ty) {
+            return Err(MoveError::OffBoard);
+        }
+
+        match self.get(fx, fy) {
+            Tile::Player(player) if player == self.current_player => {}
+            Tile::Player(_) => return Err(MoveError::WrongPlayer),
+            _ => return Err(MoveError::EmptyOrigin),
+        }
+
+        if !self.moves_from(fx, fy).contains(&mov) {
+            return Err(MoveError::Unreachable);
+        }
+
+        Ok(())
+    }
+
     pub fn possible_moves(&self) -> Vec<Move> {
         let mut result = Vec::new();
 
-        for x in 0..BOARD_WIDTH as i8 {
+        for x in 0..MAX_BOARD_WIDTH as i8 {
             for y in 0..BOARD_HEIGHT as i8 {
                 if self.get(x, y) == Tile::Player(self.current_player) {
                     result.append(&mut self.moves_from(x, y));
@@ -166,15 +576,72 @@ impl GameState {
         let from = self.get(fx, fy);
         self.set(tx, ty, from);
         self.set(fx, fy, Tile::Empty);
-        self.current_player = 1-self.current_player;
+        self.current_player = self.next_player();
+        self.ply += 1;
     }
 
     pub fn current_player(&self) -> u8 {
         self.current_player
     }
+
+    /// The number of moves played since the start of the game.
+    pub fn ply(&self) -> usize {
+        self.ply
+    }
+
+    /// The player who made the first move of the game.
+    fn first_player(&self) -> u8 {
+        ((self.current_player as usize + self.num_players as usize - self.ply % self.num_players as usize) % self.num_players as usize) as u8
+    }
+
+    /// The number of the current round, starting at 1. A new round starts with every move of
+    /// player 0, so if another player made the first move, the first round is a partial one.
+    pub fn full_move_number(&self) -> usize {
+        (self.first_player() as usize + self.ply) / self.num_players as usize + 1
+    }
+
+    /// The number of moves `player` has made since the start of the game.
+    pub fn moves_played(&self, player: u8) -> usize {
+        let num_players = self.num_players as usize;
+        let first_player = self.first_player() as usize;
+        let player = player as usize;
+        (first_player + self.ply + num_players - 1 - player) / num_players - (first_player + num_players - 1 - player) / num_players
+    }
+
+    pub fn num_players(&self) -> u8 {
+        self.num_players
+    }
+
+    fn next_player(&self) -> u8 {
+        (self.current_player + 1) % self.num_players
+    }
+
+    fn previous_player(&self) -> u8 {
+        (self.current_player + self.num_players - 1) % self.num_players
+    }
 }
 
 impl Game {
+    pub fn new(num_players: u8) -> Self {
+        Game::from_state(GameState::new(num_players))
+    }
+
+    /// Creates a game starting from the (possibly non-standard) position `state`.
+    pub fn from_state(state: GameState) -> Self {
+        let mut positions = HashMap::new();
+        positions.insert(state.position(), 1);
+        Game {
+            start: state,
+            state,
+            moves: Vec::new(),
+            nodes: vec![HistoryNode { mov: None, parent: None, children: Vec::new(), selected: 0 }],
+            current: 0,
+            draw_rules: DrawRules::default(),
+            clock: None,
+            positions,
+        }
+    }
+
     pub fn move_piece(&mut self, mov: Move) {
         let (fx, fy) = mov.from;
         let (tx, ty) = mov.to;
@@ -183,25 +650,209 @@ impl Game {
             panic!("Invalid locations for move_piece");
         }
 
+        self.play(mov);
+    }
+
+    /// Plays `mov` if it is legal in the current position and leaves the game untouched
+    /// otherwise.
+    pub fn try_move(&mut self, mov: Move) -> Result<(), MoveError> {
+        if self.termination().is_some() {
+            return Err(MoveError::GameOver);
+        }
+
+        self.state.check_move(mov)?;
+        self.play(mov);
+        Ok(())
+    }
+
+    /// Like `try_move`, but charges the clock of the game, if any, with the `elapsed` time the
+    /// player took for the move. If the player ran out of time, the move is not played.
+    pub fn try_timed_move(&mut self, mov: Move, elapsed: Duration) -> Result<(), MoveError> {
+        if self.termination().is_some() {
+            return Err(MoveError::GameOver);
+        }
+
+        self.state.check_move(mov)?;
+        let player = self.state.current_player;
+        if let Some(ref mut clock) = self.clock {
+            if !clock.record_move(player, elapsed) {
+                return Err(MoveError::FlagFall);
+            }
+        }
+
+        self.play(mov);
+        Ok(())
+    }
+
+    /// Checks whether the player to move still has time after thinking for `elapsed`, ending
+    /// the game otherwise. Games without a clock and games which are already over are not
+    /// affected.
+    pub fn check_flag(&mut self, elapsed: Duration) -> bool {
+        if self.termination().is_some() {
+            return true;
+        }
+
+        let player = self.state.current_player;
+        match self.clock {
+            Some(ref mut clock) => clock.check_flag(player, elapsed),
+            None => true,
+        }
+    }
+
+    /// Plays `mov`, following the matching continuation of the current position or starting a
+    /// new variation if there is none.
+    fn play(&mut self, mov: Move) {
+        let existing = self.nodes[self.current].children.iter().position(|&child| self.nodes[child].mov == Some(mov));
+        let selected = match existing {
+            Some(selected) => selected,
+            None => {
+                let child = self.nodes.len();
+                self.nodes.push(HistoryNode { mov: Some(mov), parent: Some(self.current), children: Vec::new(), selected: 0 });
+                self.nodes[self.current].children.push(child);
+                self.nodes[self.current].children.len() - 1
+            }
+        };
+
+        self.nodes[self.current].selected = selected;
+        self.current = self.nodes[self.current].children[selected];
         self.moves.push(mov);
         self.state.move_piece(mov);
+        *self.positions.entry(self.state.position()).or_insert(0) += 1;
     }
 
+    /// Takes back the last move. It stays in the history and can be replayed with `redo`.
     pub fn undo(&mut self) {
         if let Some(mov) = self.moves.pop() {
+            let position = self.state.position();
+            let count = self.positions.get_mut(&position).unwrap();
+            *count -= 1;
+            if *count == 0 {
+                self.positions.remove(&position);
+            }
+
             let (fx, fy) = mov.from;
             let (tx, ty) = mov.to;
             let from = self.state.get(tx, ty);
             self.state.set(fx, fy, from);
             self.state.set(tx, ty, Tile::Empty);
-            self.state.current_player = 1-self.state.current_player;
+            self.state.current_player = self.state.previous_player();
+            self.state.ply -= 1;
+            self.current = self.nodes[self.current].parent.unwrap();
+        }
+    }
+
+    /// Replays the continuation of the current position which was played or visited last.
+    pub fn redo(&mut self) {
+        let node = &self.nodes[self.current];
+        if let Some(&child) = node.children.get(node.selected) {
+            let mov = self.nodes[child].mov.unwrap();
+            self.play(mov);
+        }
+    }
+
+    /// Moves along the current line to the position at `ply`. If the line does not reach that
+    /// far, it stops at its start or end.
+    pub fn go_to_ply(&mut self, ply: usize) {
+        while self.state.ply > ply && !self.moves.is_empty() {
+            self.undo();
+        }
+
+        while self.state.ply < ply && self.can_redo() {
+            self.redo();
+        }
+    }
+
+    pub fn go_to_start(&mut self) {
+        self.go_to_ply(0);
+    }
+
+    /// Moves to the end of the current line.
+    pub fn go_to_end(&mut self) {
+        while self.can_redo() {
+            self.redo();
         }
     }
 
+    pub fn can_redo(&self) -> bool {
+        !self.nodes[self.current].children.is_empty()
+    }
+
+    /// The moves played from the current position so far, starting with the main line. Playing
+    /// one of them switches to its variation.
+    pub fn variations(&self) -> Vec<Move> {
+        self.nodes[self.current].children.iter().map(|&child| self.nodes[child].mov.unwrap()).collect()
+    }
+
     pub fn last_move(&self) -> Option<&Move> {
         self.moves.last()
     }
 
+    /// The moves leading from the start position to the current position.
+    pub fn moves(&self) -> &[Move] {
+        &self.moves
+    }
+
+    pub fn draw_rules(&self) -> DrawRules {
+        self.draw_rules
+    }
+
+    pub fn set_draw_rules(&mut self, draw_rules: DrawRules) {
+        self.draw_rules = draw_rules;
+    }
+
+    pub fn clock(&self) -> Option<&Clock> {
+        self.clock.as_ref()
+    }
+
+    /// Sets the clock charged by `try_timed_move`. Taking back moves does not change it.
+    ///
+    /// Panics if the clock is not made for the number of players of the game.
+    pub fn set_clock(&mut self, clock: Option<Clock>) {
+        if let Some(ref clock) = clock {
+            assert_eq!(clock.num_players(), self.state.num_players, "The clock does not match the number of players");
+        }
+        self.clock = clock;
+    }
+
+    /// How often the current position occurred in the moves leading to it, including itself.
+    pub fn repetitions(&self) -> usize {
+        self.positions[&self.state.position()]
+    }
+
+    /// Returns why the game is over, or `None` if it is still running.
+    pub fn termination(&self) -> Option<Termination> {
+        if let Some(winner) = self.state.winner() {
+            return Some(Termination::Won(winner));
+        }
+
+        if let Some(player) = self.clock.as_ref().and_then(Clock::flagged) {
+            return Some(Termination::FlagFall(player));
+        }
+
+        if self.state.drawn() {
+            return Some(Termination::MoveLimit);
+        }
+
+        if let Some(repetitions) = self.draw_rules.repetitions {
+            if self.repetitions() >= repetitions {
+                return Some(Termination::Repetition);
+            }
+        }
+
+        if let Some(max_plies) = self.draw_rules.max_plies {
+            if self.state.ply() >= max_plies {
+                return Some(Termination::MoveLimit);
+            }
+        }
+
+        None
+    }
+
+    /// The position the game started from.
+    pub fn start(&self) -> &GameState {
+        &self.start
+    }
+
     pub fn state(&self) -> &GameState {
         &self.state
     }
@@ -209,27 +860,302 @@ impl Game {
 
 impl Default for GameState {
     fn default() -> Self {
-        let mut state = GameState {
-            board: [[Tile::Invalid; BOARD_HEIGHT as usize]; BOARD_WIDTH as usize],
-            ply: 0,
-            current_player: 0,
-        };
+        GameState::new(2)
+    }
+}
 
-        for y in 0..BOARD_HEIGHT as i8 {
-            for x in 0..BOARD_WIDTH as i8 {
-                if !state.is_valid_location(x, y) {
-                    continue;
+impl Default for Game {
+    fn default() -> Self {
+        Game::new(2)
+    }
+}
+
+mod tests {
+    #[test]
+    fn test_corners() {
+        use {BOARD_HEIGHT, MAX_BOARD_WIDTH, GameState};
+
+        let state = GameState::new(6);
+        let valid = (0..MAX_BOARD_WIDTH as i8).flat_map(|x| (0..BOARD_HEIGHT as i8).map(move |y| (x, y)))
+            .filter(|&(x, y)| state.is_valid_location(x, y))
+            .count();
+        assert_eq!(valid, 121);
+
+        let mut holes: Vec<_> = (0..6).flat_map(|player| state.home(player)).collect();
+        assert!(holes.iter().all(|&(x, y)| state.is_valid_location(x, y)));
+        holes.sort();
+        holes.dedup();
+        assert_eq!(holes.len(), 60);
+    }
+
+    #[test]
+    fn test_player_counts() {
+        use {Geometry, GameState, Tile};
+
+        for &geometry in &[Geometry::Star, Geometry::SmallStar { size: 3 }, Geometry::Square] {
+            for &num_players in geometry.player_counts() {
+                let mut state = GameState::with_geometry(geometry, num_players);
+                for player in 0..num_players {
+                    assert!(state.home(player).iter().all(|&(x, y)| state.get(x, y) == Tile::Player(player)));
+                    assert!(state.targets(player).iter().all(|&(x, y)| state.get(x, y) != Tile::Player(player)));
+                    assert_eq!(state.home(player).len(), state.targets(player).len());
                 }
-                if y < 5 && (x-6).abs() < 3 {
-                    state.set(x, y, Tile::Player(0));
-                } else if y > 11  && (x-6).abs() < 3 {
-                    state.set(x, y, Tile::Player(1));
-                } else {
-                    state.set(x, y, Tile::Empty);
+
+                for player in 0..num_players {
+                    assert_eq!(state.current_player(), player);
+                    let mov = state.possible_moves()[0];
+                    state.move_piece(mov);
                 }
+                assert_eq!(state.current_player(), 0);
             }
         }
+    }
 
-        state
+    #[test]
+    fn test_try_move() {
+        use {Game, Move, MoveError};
+
+        let mut game = Game::default();
+        assert_eq!(game.try_move(Move { from: (6, 0), to: (0, 0) }), Err(MoveError::OffBoard));
+        assert_eq!(game.try_move(Move { from: (6, 8), to: (6, 9) }), Err(MoveError::EmptyOrigin));
+        assert_eq!(game.try_move(Move { from: (6, 12), to: (6, 11) }), Err(MoveError::WrongPlayer));
+        assert_eq!(game.try_move(Move { from: (6, 4), to: (6, 10) }), Err(MoveError::Unreachable));
+        assert_eq!(game.try_move(Move { from: (6, 0), to: (6, 1) }), Err(MoveError::Unreachable));
+        assert!(game.last_move().is_none());
+
+        assert_eq!(game.try_move(Move { from: (6, 4), to: (6, 5) }), Ok(()));
+        assert_eq!(game.state().current_player(), 1);
+    }
+
+    #[test]
+    fn test_history() {
+        use {Game, Move};
+
+        let mut game = Game::default();
+        let a = Move { from: (6, 4), to: (6, 5) };
+        let b = Move { from: (6, 12), to: (6, 11) };
+        let c = Move { from: (5, 4), to: (5, 5) };
+        let d = Move { from: (7, 12), to: (7, 11) };
+        game.move_piece(a);
+        game.move_piece(b);
+        game.move_piece(c);
+        let end = game.clone();
+
+        game.go_to_start();
+        assert_eq!(*game.state(), *game.start());
+        assert_eq!(game.variations(), vec![a]);
+        game.go_to_end();
+        assert_eq!(game, end);
+
+        game.go_to_ply(1);
+        assert_eq!(game.moves(), &[a]);
+        game.move_piece(d);
+        assert_eq!(game.moves(), &[a, d]);
+        game.undo();
+        assert_eq!(game.variations(), vec![b, d]);
+
+        // Redo follows the variation visited last.
+        game.redo();
+        assert_eq!(game.last_move(), Some(&d));
+        assert!(!game.can_redo());
+        game.undo();
+        game.move_piece(b);
+        game.go_to_end();
+        assert_eq!(game.state(), end.state());
+        assert_eq!(game.moves(), end.moves());
+    }
+
+    #[test]
+    fn test_clock() {
+        use std::time::Duration;
+        use {Game, Move, MoveError, Outcome, Termination};
+        use clock::Clock;
+
+        let mut game = Game::default();
+        game.set_clock(Some(Clock::new("10+1".parse().unwrap(), 2)));
+        assert_eq!(game.try_timed_move(Move { from: (6, 4), to: (6, 5) }, Duration::from_secs(3)), Ok(()));
+        assert_eq!(game.clock().unwrap().remaining(0), Duration::from_secs(8));
+        assert_eq!(game.try_timed_move(Move { from: (6, 4), to: (6, 5) }, Duration::from_secs(1)), Err(MoveError::EmptyOrigin));
+        assert_eq!(game.clock().unwrap().remaining(1), Duration::from_secs(10));
+
+        assert!(game.check_flag(Duration::from_secs(10)));
+        assert!(!game.check_flag(Duration::from_secs(11)));
+        assert_eq!(game.termination(), Some(Termination::FlagFall(1)));
+        assert_eq!(game.termination().unwrap().outcome(0), Outcome::Win);
+        assert_eq!(game.try_timed_move(Move { from: (6, 12), to: (6, 11) }, Duration::from_secs(1)), Err(MoveError::GameOver));
+
+        let mut game = Game::default();
+        game.set_clock(Some(Clock::new("5".parse().unwrap(), 2)));
+        assert_eq!(game.try_timed_move(Move { from: (6, 4), to: (6, 5) }, Duration::from_secs(6)), Err(MoveError::FlagFall));
+        assert!(game.moves().is_empty());
+        assert_eq!(game.termination(), Some(Termination::FlagFall(0)));
+    }
+
+    #[test]
+    #[should_panic(expected = "The clock does not match the number of players")]
+    fn test_clock_players() {
+        use Game;
+        use clock::Clock;
+
+        let mut game = Game::new(3);
+        game.set_clock(Some(Clock::new("10+1".parse().unwrap(), 2)));
+    }
+
+    #[test]
+    fn test_counters() {
+        use {Game, GameState};
+        use ai::AI;
+        use builder::PositionBuilder;
+
+        let mut game = Game::new(3);
+        for _ in 0..7 {
+            let mov = game.state().possible_moves()[0];
+            game.move_piece(mov);
+        }
+        assert_eq!(game.state().ply(), 7);
+        assert_eq!(game.state().full_move_number(), 3);
+        assert_eq!((0..3).map(|player| game.state().moves_played(player)).collect::<Vec<_>>(), vec![3, 2, 2]);
+
+        game.undo();
+        assert_eq!(game.state().ply(), 6);
+        assert_eq!(game.state().moves_played(0), 2);
+        let state: GameState = ::serde_json::from_str(&::serde_json::to_string(game.state()).unwrap()).unwrap();
+        assert_eq!(state.ply(), 6);
+
+        game.go_to_ply(2);
+        assert_eq!(game.moves().len(), 2);
+        game.go_to_ply(10);
+        assert_eq!(game.state().ply(), 7);
+
+        // Player 1 moved first, so the first round only had a single move.
+        let state = PositionBuilder::from_state(&GameState::default()).ply(1).build().unwrap();
+        assert_eq!(state.full_move_number(), 2);
+        assert_eq!((state.moves_played(0), state.moves_played(1)), (0, 1));
+
+        let mut game = Game::default();
+        let mut ai = AI::new(*game.state());
+        for _ in 0..4 {
+            let mov = game.state().possible_moves()[0];
+            game.move_piece(mov);
+            ai.make_move(mov);
+        }
+        assert_eq!(ai.ply(), game.state().ply());
+    }
+
+    #[test]
+    fn test_draw_rules() {
+        use {DrawRules, Game, Move, MoveError, Outcome, Termination};
+
+        let mut game = Game::default();
+        let moves = [
+            Move { from: (6, 4), to: (6, 5) },
+            Move { from: (6, 12), to: (6, 11) },
+            Move { from: (6, 5), to: (6, 4) },
+            Move { from: (6, 11), to: (6, 12) },
+        ];
+
+        for _ in 0..2 {
+            for &mov in &moves {
+                assert_eq!(game.termination(), None);
+                game.try_move(mov).unwrap();
+            }
+        }
+        assert_eq!(game.repetitions(), 3);
+        assert_eq!(game.termination(), Some(Termination::Repetition));
+        assert_eq!(game.try_move(moves[0]), Err(MoveError::GameOver));
+
+        let json = ::serde_json::to_string(&game).unwrap();
+        let restored: Game = ::serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, game);
+        assert_eq!(restored.repetitions(), 3);
+
+        // Malformed games are rejected instead of being replayed blindly.
+        assert!(::serde_json::from_str::<Game>(&json.replacen("\"to\":[6,5]", "\"to\":[20,5]", 1)).is_err());
+        assert!(::serde_json::from_str::<Game>(&json.replacen("\"to\":[6,5]", "\"to\":[6,7]", 1)).is_err());
+        assert!(::serde_json::from_str::<Game>(&json.replace("\"current\":8", "\"current\":99")).is_err());
+
+        game.undo();
+        assert_eq!(game.repetitions(), 2);
+        game.go_to_start();
+        assert_eq!(game.repetitions(), 1);
+        game.go_to_end();
+        assert_eq!(game.repetitions(), 3);
+        assert_eq!(Termination::Repetition.outcome(0), Outcome::Draw);
+
+        game.set_draw_rules(DrawRules { repetitions: None, max_plies: Some(10) });
+        assert_eq!(game.termination(), None);
+        game.try_move(moves[0]).unwrap();
+        game.try_move(moves[1]).unwrap();
+        assert_eq!(game.termination(), Some(Termination::MoveLimit));
+
+        assert_eq!(Termination::Won(1).outcome(0), Outcome::Loss);
+        assert_eq!(Termination::Won(1).outcome(1), Outcome::Win);
+    }
+
+    #[test]
+    fn test_win_rules() {
+        use {GameState, Tile, WinRule};
+
+        let mut state = GameState::default();
+        // Fill the target of player 0 with stones of player 1, except for one hole.
+        let targets = state.targets(0);
+        for &(x, y) in &targets[1..] {
+            state.set(x, y, Tile::Player(1));
+        }
+        state.set(targets[0].0, targets[0].1, Tile::Player(0));
+
+        state.set_win_rule(WinRule::OneStone);
+        assert!(state.won(0));
+        state.set_win_rule(WinRule::AllStones);
+        assert!(!state.won(0));
+
+        // Player 0 has left its home, player 1 still has stones in it.
+        let mut state = GameState::default();
+        for &(x, y) in &state.home(0) {
+            state.set(x, y, Tile::Empty);
+        }
+        state.set(6, 8, Tile::Player(0));
+        state.set_win_rule(WinRule::NoParking { ply: 30 });
+        assert!(!state.won(0));
+        state.ply = 30;
+        assert!(state.won(0));
+        assert!(!state.won(1));
+        assert!(!state.drawn());
+
+        // If both players are still parked, nobody can win anymore.
+        let mut state = GameState::default();
+        state.set_win_rule(WinRule::NoParking { ply: 30 });
+        state.ply = 29;
+        assert!(!state.drawn());
+        state.ply = 30;
+        assert_eq!(state.winner(), None);
+        assert!(state.drawn());
+
+        let mut state = GameState::default();
+        state.set_win_rule(WinRule::PartialFill { ply: 10 });
+        state.ply = 10;
+        assert_eq!(state.winner(), None);
+        assert!(state.drawn());
+        let (x, y) = state.targets(1)[0];
+        state.set(x, y, Tile::Player(1));
+        assert_eq!(state.winner(), Some(1));
+        assert!(!state.drawn());
+    }
+
+    #[test]
+    fn test_path() {
+        use {GameState, Move};
+
+        let state = GameState::default();
+        assert_eq!(state.path(Move { from: (6, 4), to: (6, 5) }), Some(vec![(6, 4), (6, 5)]));
+        assert_eq!(state.path(Move { from: (6, 3), to: (7, 5) }), Some(vec![(6, 3), (7, 5)]));
+        assert_eq!(state.path(Move { from: (6, 2), to: (6, 6) }), None);
+        assert_eq!(state.path(Move { from: (6, 8), to: (6, 9) }), None);
+
+        for mov in state.possible_moves() {
+            let path = state.path(mov).unwrap();
+            assert_eq!(path[0], mov.from);
+            assert_eq!(*path.last().unwrap(), mov.to);
+        }
     }
 }
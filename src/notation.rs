@@ -0,0 +1,460 @@
+//! Text notation for moves and positions.
+//!
+//! Holes are written as a file letter followed by a rank number. Files `a` to `p` are the columns
+//! of the board from left to right, ranks `1` to `17` its rows from top to bottom, i.e. `(x, y)`
+//! is written as the `x`-th letter followed by `y+1`. Star boards only use the files `a` to `m`.
+//!
+//! A move is written as its origin and destination separated by a dash, e.g. `g5-g6`. Jump
+//! sequences may instead list every hole the stone lands on, separated by colons, e.g.
+//! `g4:g6:g8`.
+//!
+//! A position is written in the spirit of FEN as three to five space separated fields:
+//!   * The placement of the stones, row by row from top to bottom and separated by slashes. Each
+//!     row lists the holes of the board from left to right, players' stones as the letters `a` to
+//!     `f` (player 0 to 5) and runs of empty holes as their length. Rows without holes are left
+//!     out.
+//!   * The player to move as a letter.
+//!   * The ply.
+//!   * The board, one of `star`, `small-star-<size>` or `square`. It is omitted for the standard
+//!     star.
+//!   * The win rule, one of `one-stone`, `all-stones`, `no-parking <ply>` or `partial-fill <ply>`.
+//!     It is omitted for `one-stone`.
+//!
+//! The number of players is not written explicitly, since every player's stones stay on the
+//! board for the whole game.
+
+use std::fmt;
+use std::str::FromStr;
+
+use {BOARD_HEIGHT, MAX_BOARD_WIDTH, GameState, Geometry, Move, Tile, WinRule};
+
+/// The reason a move or position could not be parsed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum NotationError {
+    /// A hole is not of the form file letter followed by rank number, or it is not on the board.
+    InvalidHole,
+    /// A move does not consist of at least two holes separated by `-` or `:`.
+    InvalidMove,
+    /// Two consecutive holes of a jump sequence are not connected by a single jump.
+    InvalidHop,
+    /// The stone placement does not describe every hole of the board exactly once.
+    InvalidPlacement,
+    /// A player letter is out of range, or the stones present do not form a supported game.
+    InvalidPlayer,
+    /// The ply is not a number.
+    InvalidPly,
+    /// The position does not consist of placement, player to move and ply, optionally followed by
+    /// the board and the win rule.
+    MissingField,
+    /// The win rule is not one of `one-stone`, `all-stones`, `no-parking <ply>` or
+    /// `partial-fill <ply>`.
+    InvalidWinRule,
+    /// The board is not one of `star`, `small-star-<size>` or `square`.
+    InvalidGeometry,
+    /// The time control is not of the form `[<moves>/]<base>[+<increment>][d<delay>]`.
+    InvalidTimeControl,
+}
+
+impl fmt::Display for NotationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            NotationError::InvalidHole => write!(f, "invalid hole"),
+            NotationError::InvalidMove => write!(f, "invalid move"),
+            NotationError::InvalidHop => write!(f, "holes are not connected by a jump"),
+            NotationError::InvalidPlacement => write!(f, "invalid stone placement"),
+            NotationError::InvalidPlayer => write!(f, "invalid player"),
+            NotationError::InvalidPly => write!(f, "invalid ply"),
+            NotationError::MissingField => write!(f, "wrong number of fields"),
+            NotationError::InvalidWinRule => write!(f, "invalid win rule"),
+            NotationError::InvalidGeometry => write!(f, "invalid board"),
+            NotationError::InvalidTimeControl => write!(f, "invalid time control"),
+        }
+    }
+}
+
+impl ::std::error::Error for NotationError {}
+
+pub fn format_hole(x: i8, y: i8) -> String {
+    format!("{}{}", (b'a' + x as u8) as char, y + 1)
+}
+
+pub fn parse_hole(s: &str) -> Result<(i8, i8), NotationError> {
+    let mut chars = s.chars();
+    let x = match chars.next() {
+        Some(c @ 'a'..='z') => c as i8 - 'a' as i8,
+        _ => return Err(NotationError::InvalidHole),
+    };
+
+    let rank: &str = chars.as_str();
+    if rank.is_empty() || !rank.chars().all(|c| c.is_ascii_digit()) {
+        return Err(NotationError::InvalidHole);
+    }
+
+    let y = match rank.parse::<i8>() {
+        Ok(rank) if rank >= 1 => rank - 1,
+        _ => return Err(NotationError::InvalidHole),
+    };
+
+    if x >= MAX_BOARD_WIDTH as i8 || y >= BOARD_HEIGHT as i8 {
+        return Err(NotationError::InvalidHole);
+    }
+
+    Ok((x, y))
+}
+
+fn format_player(player: u8) -> char {
+    (b'a' + player) as char
+}
+
+fn parse_player(c: char) -> Result<u8, NotationError> {
+    match c {
+        'a'..='f' => Ok(c as u8 - b'a'),
+        _ => Err(NotationError::InvalidPlayer),
+    }
+}
+
+/// Splits a move into the holes it visits.
+fn parse_holes(s: &str) -> Result<Vec<(i8, i8)>, NotationError> {
+    let holes = if s.contains('-') {
+        let holes: Vec<_> = s.split('-').collect();
+        if holes.len() != 2 {
+            return Err(NotationError::InvalidMove);
+        }
+        holes
+    } else {
+        s.split(':').collect()
+    };
+
+    if holes.len() < 2 {
+        return Err(NotationError::InvalidMove);
+    }
+
+    holes.into_iter().map(parse_hole).collect()
+}
+
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}-{}", format_hole(self.from.0, self.from.1), format_hole(self.to.0, self.to.1))
+    }
+}
+
+impl FromStr for Move {
+    type Err = NotationError;
+
+    /// Parses both the `from-to` and the `from:hop:...:to` notation. Intermediate hops are
+    /// ignored, use `GameState::parse_move` to check them against a position.
+    fn from_str(s: &str) -> Result<Move, NotationError> {
+        let holes = parse_holes(s.trim())?;
+        Ok(Move {
+            from: holes[0],
+            to: holes[holes.len()-1],
+        })
+    }
+}
+
+impl fmt::Display for WinRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            WinRule::OneStone => write!(f, "one-stone"),
+            WinRule::AllStones => write!(f, "all-stones"),
+            WinRule::NoParking { ply } => write!(f, "no-parking {}", ply),
+            WinRule::PartialFill { ply } => write!(f, "partial-fill {}", ply),
+        }
+    }
+}
+
+impl FromStr for WinRule {
+    type Err = NotationError;
+
+    fn from_str(s: &str) -> Result<WinRule, NotationError> {
+        let fields: Vec<_> = s.split_whitespace().collect();
+        let ply = || fields.get(1).and_then(|ply| ply.parse().ok()).ok_or(NotationError::InvalidWinRule);
+        match (fields.first(), fields.len()) {
+            (Some(&"one-stone"), 1) => Ok(WinRule::OneStone),
+            (Some(&"all-stones"), 1) => Ok(WinRule::AllStones),
+            (Some(&"no-parking"), 2) => Ok(WinRule::NoParking { ply: ply()? }),
+            (Some(&"partial-fill"), 2) => Ok(WinRule::PartialFill { ply: ply()? }),
+            _ => Err(NotationError::InvalidWinRule),
+        }
+    }
+}
+
+impl fmt::Display for Geometry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Geometry::Star => write!(f, "star"),
+            Geometry::SmallStar { size } => write!(f, "small-star-{}", size),
+            Geometry::Square => write!(f, "square"),
+        }
+    }
+}
+
+impl FromStr for Geometry {
+    type Err = NotationError;
+
+    fn from_str(s: &str) -> Result<Geometry, NotationError> {
+        match s {
+            "star" => Ok(Geometry::Star),
+            "square" => Ok(Geometry::Square),
+            _ => {
+                let size = s.strip_prefix("small-star-").and_then(|size| size.parse().ok());
+                match size {
+                    Some(size) if Geometry::SmallStar { size }.is_valid() => Ok(Geometry::SmallStar { size }),
+                    _ => Err(NotationError::InvalidGeometry),
+                }
+            }
+        }
+    }
+}
+
+/// Parses the optional board and win rule following the ply of a position. Either may be left
+/// out, in which case the default is used.
+pub(crate) fn parse_options(fields: &[&str]) -> Result<(Geometry, WinRule), NotationError> {
+    if fields.is_empty() {
+        return Ok((Geometry::default(), WinRule::default()));
+    }
+
+    if let Ok(win_rule) = fields.join(" ").parse() {
+        return Ok((Geometry::default(), win_rule));
+    }
+
+    let geometry = fields[0].parse()?;
+    let win_rule = match fields.len() {
+        1 => WinRule::default(),
+        _ => fields[1..].join(" ").parse()?,
+    };
+    Ok((geometry, win_rule))
+}
+
+impl GameState {
+    /// Writes `mov` including its intermediate hops if it is a jump sequence.
+    ///
+    /// Steps are written as `from-to`, jumps as `from:hop:...:to` along the shortest path found
+    /// by `GameState::path`. Moves which are not possible in this position are written as
+    /// `from-to`.
+    pub fn move_to_notation(&self, mov: Move) -> String {
+        let (fx, fy) = mov.from;
+        match self.path(mov) {
+            Some(ref path) if !self.steps_from(fx, fy).contains(&mov.to) => {
+                path.iter().map(|&(x, y)| format_hole(x, y)).collect::<Vec<_>>().join(":")
+            }
+            _ => mov.to_string(),
+        }
+    }
+
+    /// Parses a move in either notation and checks that every hole is on the board and every hop
+    /// of a jump sequence is a single jump in this position.
+    pub fn parse_move(&self, s: &str) -> Result<Move, NotationError> {
+        let s = s.trim();
+        let holes = parse_holes(s)?;
+        if !holes.iter().all(|&(x, y)| self.is_valid_location(x, y)) {
+            return Err(NotationError::InvalidHole);
+        }
+        if s.contains(':') {
+            for hop in holes.windows(2) {
+                if !self.jumps_from(hop[0].0, hop[0].1).contains(&hop[1]) {
+                    return Err(NotationError::InvalidHop);
+                }
+            }
+        }
+
+        Ok(Move {
+            from: holes[0],
+            to: holes[holes.len()-1],
+        })
+    }
+
+    /// Writes the position as placement, player to move, ply, board and win rule.
+    pub fn to_notation(&self) -> String {
+        let mut rows = Vec::with_capacity(BOARD_HEIGHT as usize);
+        for y in 0..BOARD_HEIGHT as i8 {
+            if !(0..MAX_BOARD_WIDTH as i8).any(|x| self.is_valid_location(x, y)) {
+                continue;
+            }
+
+            let mut row = String::new();
+            let mut empty = 0;
+            for x in 0..MAX_BOARD_WIDTH as i8 {
+                match self.get(x, y) {
+                    Tile::Invalid => {}
+                    Tile::Empty => empty += 1,
+                    Tile::Player(player) => {
+                        if empty > 0 {
+                            row.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        row.push(format_player(player));
+                    }
+                }
+            }
+
+            if empty > 0 {
+                row.push_str(&empty.to_string());
+            }
+            rows.push(row);
+        }
+
+        let mut result = format!("{} {} {}", rows.join("/"), format_player(self.current_player), self.ply);
+        if self.geometry != Geometry::default() {
+            result.push_str(&format!(" {}", self.geometry));
+        }
+        if self.win_rule != WinRule::default() {
+            result.push_str(&format!(" {}", self.win_rule));
+        }
+        result
+    }
+
+    /// Parses a position written by `GameState::to_notation`.
+    pub fn from_notation(s: &str) -> Result<GameState, NotationError> {
+        let fields: Vec<_> = s.split_whitespace().collect();
+        if fields.len() < 3 || fields.len() > 6 {
+            return Err(NotationError::MissingField);
+        }
+
+        let (geometry, win_rule) = parse_options(&fields[3..])?;
+        let mut state = GameState::empty(geometry);
+        state.win_rule = win_rule;
+
+        let ys: Vec<_> = (0..BOARD_HEIGHT as i8)
+            .filter(|&y| (0..MAX_BOARD_WIDTH as i8).any(|x| state.is_valid_location(x, y)))
+            .collect();
+        let rows: Vec<_> = fields[0].split('/').collect();
+        if rows.len() != ys.len() {
+            return Err(NotationError::InvalidPlacement);
+        }
+
+        let mut num_players = 0;
+        for (&y, row) in ys.iter().zip(rows.iter()) {
+            let holes: Vec<_> = (0..MAX_BOARD_WIDTH as i8).filter(|&x| state.is_valid_location(x, y)).collect();
+            let mut holes = holes.into_iter();
+            let mut chars = row.chars().peekable();
+
+            while let Some(c) = chars.next() {
+                if let Some(digit) = c.to_digit(10) {
+                    let mut empty = digit;
+                    while let Some(digit) = chars.peek().and_then(|c| c.to_digit(10)) {
+                        empty = empty.checked_mul(10).and_then(|empty| empty.checked_add(digit))
+                            .ok_or(NotationError::InvalidPlacement)?;
+                        chars.next();
+                    }
+
+                    if empty == 0 {
+                        return Err(NotationError::InvalidPlacement);
+                    }
+
+                    for _ in 0..empty {
+                        let x = holes.next().ok_or(NotationError::InvalidPlacement)?;
+                        state.set(x, y, Tile::Empty);
+                    }
+                } else {
+                    let player = parse_player(c)?;
+                    let x = holes.next().ok_or(NotationError::InvalidPlacement)?;
+                    state.set(x, y, Tile::Player(player));
+                    num_players = ::std::cmp::max(num_players, player+1);
+                }
+            }
+
+            if holes.next().is_some() {
+                return Err(NotationError::InvalidPlacement);
+            }
+        }
+
+        if !geometry.player_counts().contains(&num_players) {
+            return Err(NotationError::InvalidPlayer);
+        }
+        state.num_players = num_players;
+
+        let mut side = fields[1].chars();
+        state.current_player = match (side.next(), side.next()) {
+            (Some(c), None) => parse_player(c)?,
+            _ => return Err(NotationError::InvalidPlayer),
+        };
+        if state.current_player >= num_players {
+            return Err(NotationError::InvalidPlayer);
+        }
+
+        state.ply = fields[2].parse().map_err(|_| NotationError::InvalidPly)?;
+
+        Ok(state)
+    }
+}
+
+mod tests {
+    #[test]
+    fn test_move_notation() {
+        use {GameState, Move};
+        use notation::NotationError;
+
+        let mov = Move { from: (6, 4), to: (6, 5) };
+        assert_eq!(mov.to_string(), "g5-g6");
+        assert_eq!("g5-g6".parse(), Ok(mov));
+        assert_eq!("g4:g6:g8".parse(), Ok(Move { from: (6, 3), to: (6, 7) }));
+        assert_eq!("g5".parse::<Move>(), Err(NotationError::InvalidMove));
+        assert_eq!("q1-g6".parse::<Move>(), Err(NotationError::InvalidHole));
+        assert_eq!("g5-g6-g7".parse::<Move>(), Err(NotationError::InvalidMove));
+
+        let state = GameState::default();
+        for mov in state.possible_moves() {
+            let notation = state.move_to_notation(mov);
+            assert_eq!(state.parse_move(&notation), Ok(mov));
+        }
+
+        assert_eq!(state.move_to_notation(Move { from: (6, 3), to: (7, 5) }), "g4:h6");
+        assert_eq!(state.parse_move("g4:g6"), Err(NotationError::InvalidHop));
+        assert_eq!(state.parse_move("a1-g6"), Err(NotationError::InvalidHole));
+    }
+
+    #[test]
+    fn test_position_notation() {
+        use {GameState, Geometry, WinRule};
+        use notation::NotationError;
+
+        let state = GameState::default();
+        assert_eq!(state.to_notation(), "a/aa/aaa/aaaa/4aaaaa4/12/11/10/9/10/11/12/4bbbbb4/bbbb/bbb/bb/b a 0");
+
+        for &num_players in &[2, 3, 4, 6] {
+            let mut state = GameState::new(num_players);
+            for _ in 0..20 {
+                assert_eq!(GameState::from_notation(&state.to_notation()), Ok(state));
+                let mov = state.possible_moves()[0];
+                state.move_piece(mov);
+            }
+        }
+
+        assert_eq!(GameState::from_notation("a/aa a 0"), Err(NotationError::InvalidPlacement));
+        assert_eq!(GameState::from_notation("a/aa/aaa/aaaa/4aaaaa4/12/11/10/9/10/11/12/4bbbbb4/bbbb/bbb/bb/b c 0"), Err(NotationError::InvalidPlayer));
+        assert_eq!(GameState::from_notation("a/aa/aaa/aaaa/4aaaaa4/12/11/10/9/10/11/12/4bbbbb4/bbbb/bbb/bb/b a"), Err(NotationError::MissingField));
+        assert_eq!(GameState::from_notation("a/aa/aaa/aaaa/4aaaaa4/99999999999999999999/11/10/9/10/11/12/4bbbbb4/bbbb/bbb/bb/b a 0"), Err(NotationError::InvalidPlacement));
+
+        for &geometry in &[Geometry::SmallStar { size: 3 }, Geometry::Square] {
+            let state = GameState::with_geometry(geometry, 2);
+            assert!(state.to_notation().ends_with(&format!(" {}", geometry)));
+            assert_eq!(GameState::from_notation(&state.to_notation()), Ok(state));
+            assert_eq!(geometry.to_string().parse(), Ok(geometry));
+        }
+        assert_eq!(GameState::from_notation("16/16 a 0 hexagon"), Err(NotationError::InvalidGeometry));
+
+        for &win_rule in &[WinRule::AllStones, WinRule::NoParking { ply: 60 }, WinRule::PartialFill { ply: 300 }] {
+            for &geometry in &[Geometry::Star, Geometry::Square] {
+                let mut state = GameState::with_geometry(geometry, 2);
+                state.set_win_rule(win_rule);
+                assert!(state.to_notation().ends_with(&format!(" {}", win_rule)));
+                assert_eq!(GameState::from_notation(&state.to_notation()), Ok(state));
+            }
+        }
+        assert_eq!(GameState::from_notation("a/aa/aaa/aaaa/4aaaaa4/12/11/10/9/10/11/12/4bbbbb4/bbbb/bbb/bb/b a 0 star no-parking"), Err(NotationError::InvalidWinRule));
+        assert_eq!("small-star-9".parse::<Geometry>(), Err(NotationError::InvalidGeometry));
+    }
+
+    #[test]
+    fn test_win_rule_notation() {
+        use WinRule;
+        use notation::NotationError;
+
+        for &rule in &[WinRule::OneStone, WinRule::AllStones, WinRule::NoParking { ply: 60 }, WinRule::PartialFill { ply: 300 }] {
+            assert_eq!(rule.to_string().parse(), Ok(rule));
+        }
+        assert_eq!("no-parking".parse::<WinRule>(), Err(NotationError::InvalidWinRule));
+        assert_eq!("one-stone 3".parse::<WinRule>(), Err(NotationError::InvalidWinRule));
+    }
+}
@@ -0,0 +1,565 @@
+//! Game records
+//!
+//! A game record stores a whole game together with its metadata in a PGN-like text format. It
+//! starts with a section of tags, one per line, followed by the move text:
+//!
+//! ```text
+//! [Event "Club night"]
+//! [Date "2026.10.16"]
+//! [Player0 "Alice"]
+//! [Player1 "Bob"]
+//! [TimeControl "300+5"]
+//! [Result "0"]
+//!
+//! 1. g5-g6 {[%eval 120] Opening the center} g13-g12 2. g4:g6:g8 g14:g10 *
+//! ```
+//!
+//! There is one `PlayerN` tag for every player of the game, numbered from 0. Games which do not
+//! start from the standard position of their number of players store it in a `Position` tag in
+//! the notation of `GameState::to_notation`, which also gives their board and win rule unless
+//! other tags do. Games not played under the `one-stone` win rule store it in a `WinRule` tag,
+//! e.g. `[WinRule "no-parking 60"]`, games not played on the standard star store their board in a
+//! `Board` tag, e.g. `[Board "square"]`. Unknown tags are kept as they are.
+//!
+//! Moves are written in the notation of the `notation` module. The move text may start with a
+//! comment on the game as a whole, and each move may be followed by a comment in braces, which
+//! may start with an evaluation annotation `[%eval <score>]`. Braces and backslashes inside a
+//! comment are escaped with a backslash. Move numbers count rounds as in
+//! `GameState::full_move_number`, i.e. they are written before each move of player 0. The move
+//! text ends with the termination marker `*`.
+//!
+//! A file may contain any number of records. A record ends with its termination marker or where
+//! the tag section of the next record starts.
+
+use std::fmt;
+use std::str::FromStr;
+
+use {Game, GameState, Geometry, Move, MoveError, WinRule};
+use notation::NotationError;
+
+/// A move of a game record together with its annotations.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MoveRecord {
+    pub mov: Move,
+    pub comment: Option<String>,
+    pub evaluation: Option<isize>,
+}
+
+/// A game together with its metadata.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GameRecord {
+    pub event: Option<String>,
+    pub date: Option<String>,
+    /// The names of the players in turn order. Its length is the number of players of the game.
+    pub players: Vec<String>,
+    pub time_control: Option<String>,
+    pub result: Option<String>,
+    /// The start position, if it is not the standard one for the number of players.
+    pub start: Option<GameState>,
+    pub win_rule: WinRule,
+    pub geometry: Geometry,
+    /// Any tags not covered by the fields above, in the order they appeared.
+    pub tags: Vec<(String, String)>,
+    /// The comment before the first move.
+    pub comment: Option<String>,
+    pub moves: Vec<MoveRecord>,
+}
+
+/// The reason a game record could not be read or converted into a game.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RecordError {
+    /// A tag line is not of the form `[Name "value"]`.
+    InvalidTag(String),
+    /// The number of `PlayerN` tags does not form a supported game.
+    InvalidPlayers,
+    /// The `Position` tag could not be parsed.
+    InvalidPosition(NotationError),
+    /// The `WinRule` tag could not be parsed.
+    InvalidWinRule(String),
+    /// The `Board` tag could not be parsed or does not match the `Position` tag.
+    InvalidBoard(String),
+    /// A token of the move text is neither a move, a move number nor a comment.
+    InvalidMove(String, NotationError),
+    /// A comment is missing its closing brace.
+    UnterminatedComment,
+    /// The move at the given index is not legal in the position it is played in.
+    IllegalMove(usize, MoveError),
+    /// A single game record was expected, but the given number of records was found.
+    RecordCount(usize),
+}
+
+impl fmt::Display for RecordError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RecordError::InvalidTag(ref line) => write!(f, "invalid tag: {}", line),
+            RecordError::InvalidPlayers => write!(f, "invalid number of players"),
+            RecordError::InvalidPosition(err) => write!(f, "invalid position: {}", err),
+            RecordError::InvalidWinRule(ref rule) => write!(f, "invalid win rule: {}", rule),
+            RecordError::InvalidBoard(ref board) => write!(f, "invalid board: {}", board),
+            RecordError::InvalidMove(ref token, err) => write!(f, "invalid move '{}': {}", token, err),
+            RecordError::UnterminatedComment => write!(f, "unterminated comment"),
+            RecordError::IllegalMove(index, err) => write!(f, "illegal move {}: {}", index+1, err),
+            RecordError::RecordCount(count) => write!(f, "expected a single game record, found {}", count),
+        }
+    }
+}
+
+impl ::std::error::Error for RecordError {}
+
+impl GameRecord {
+    /// Creates a record of `game` with unknown players and no further metadata.
+    pub fn from_game(game: &Game) -> GameRecord {
+        let start = *game.start();
+        let num_players = start.num_players();
+        let mut standard = GameState::with_geometry(start.geometry(), num_players);
+        standard.set_win_rule(start.win_rule());
+
+        GameRecord {
+            event: None,
+            date: None,
+            players: vec!["?".to_owned(); num_players as usize],
+            time_control: game.clock().map(|clock| clock.time_control().to_string()),
+            result: None,
+            start: if start == standard { None } else { Some(start) },
+            win_rule: start.win_rule(),
+            geometry: start.geometry(),
+            tags: Vec::new(),
+            comment: None,
+            moves: game.moves().iter().map(|&mov| MoveRecord { mov, comment: None, evaluation: None }).collect(),
+        }
+    }
+
+    /// Replays the recorded moves, checking each of them for legality.
+    ///
+    /// The draw rules of the game are not enforced, since the record may have been played under
+    /// different ones.
+    pub fn to_game(&self) -> Result<Game, RecordError> {
+        let mut game = Game::from_state(self.start_position()?);
+        for (index, record) in self.moves.iter().enumerate() {
+            game.state().check_move(record.mov).map_err(|err| RecordError::IllegalMove(index, err))?;
+            game.move_piece(record.mov);
+        }
+
+        Ok(game)
+    }
+
+    fn start_position(&self) -> Result<GameState, RecordError> {
+        let mut start = match self.start {
+            Some(start) if start.num_players() as usize == self.players.len() => start,
+            None if self.geometry.player_counts().contains(&(self.players.len() as u8)) => {
+                GameState::with_geometry(self.geometry, self.players.len() as u8)
+            }
+            _ => return Err(RecordError::InvalidPlayers),
+        };
+
+        start.set_win_rule(self.win_rule);
+        Ok(start)
+    }
+}
+
+/// Reads all game records of `s`.
+pub fn read_records(s: &str) -> Result<Vec<GameRecord>, RecordError> {
+    let mut records = Vec::new();
+    let mut tags = Vec::new();
+    let mut movetext = String::new();
+    let mut in_comment = false;
+
+    // Whether the tag section of the current record ended, i.e. a following tag starts the next
+    // record.
+    let mut in_movetext = false;
+
+    for line in s.lines() {
+        let trimmed = line.trim();
+        if !in_comment && trimmed.starts_with('[') {
+            if in_movetext {
+                records.push(parse_record(&tags, &movetext)?);
+                tags.clear();
+                movetext.clear();
+                in_movetext = false;
+            }
+            tags.push(parse_tag(trimmed)?);
+        } else {
+            in_movetext = in_movetext || !tags.is_empty() || !trimmed.is_empty();
+            in_comment = ends_in_comment(line, in_comment);
+            movetext.push_str(line);
+            movetext.push('\n');
+            if !in_comment && trimmed.ends_with('*') {
+                records.push(parse_record(&tags, &movetext)?);
+                tags.clear();
+                movetext.clear();
+                in_movetext = false;
+            }
+        }
+    }
+
+    if !tags.is_empty() || !movetext.trim().is_empty() {
+        records.push(parse_record(&tags, &movetext)?);
+    }
+
+    Ok(records)
+}
+
+/// Returns whether a comment is open at the end of `line`, given whether one was at its start.
+fn ends_in_comment(line: &str, mut in_comment: bool) -> bool {
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if in_comment => {
+                chars.next();
+            }
+            '{' => in_comment = true,
+            '}' => in_comment = false,
+            _ => {}
+        }
+    }
+
+    in_comment
+}
+
+/// Writes `records` separated by blank lines.
+pub fn write_records(records: &[GameRecord]) -> String {
+    records.iter().map(|record| record.to_string()).collect::<Vec<_>>().join("\n")
+}
+
+fn parse_tag(line: &str) -> Result<(String, String), RecordError> {
+    let invalid = || RecordError::InvalidTag(line.to_owned());
+    if !line.starts_with('[') || !line.ends_with(']') {
+        return Err(invalid());
+    }
+
+    let inner = line[1..line.len()-1].trim();
+    let space = inner.find(char::is_whitespace).ok_or_else(invalid)?;
+    let name = &inner[..space];
+    let quoted = inner[space..].trim();
+    if quoted.len() < 2 || !quoted.starts_with('"') || !quoted.ends_with('"') {
+        return Err(invalid());
+    }
+
+    let mut value = String::new();
+    let mut chars = quoted[1..quoted.len()-1].chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => value.push(chars.next().ok_or_else(invalid)?),
+            '"' => return Err(invalid()),
+            c => value.push(c),
+        }
+    }
+
+    Ok((name.to_owned(), value))
+}
+
+fn write_tag(f: &mut fmt::Formatter, name: &str, value: &str) -> fmt::Result {
+    writeln!(f, "[{} \"{}\"]", name, value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn parse_record(tags: &[(String, String)], movetext: &str) -> Result<GameRecord, RecordError> {
+    let mut record = GameRecord {
+        event: None,
+        date: None,
+        players: Vec::new(),
+        time_control: None,
+        result: None,
+        start: None,
+        win_rule: WinRule::default(),
+        geometry: Geometry::default(),
+        tags: Vec::new(),
+        comment: None,
+        moves: Vec::new(),
+    };
+
+    // The start position implies its board and win rule unless their own tags say otherwise.
+    let mut win_rule = None;
+    let mut geometry = None;
+    let mut players = Vec::new();
+    for (name, value) in tags {
+        match name.as_str() {
+            "Event" => record.event = Some(value.clone()),
+            "Date" => record.date = Some(value.clone()),
+            "TimeControl" => record.time_control = Some(value.clone()),
+            "Result" => record.result = Some(value.clone()),
+            "Position" => {
+                let start = GameState::from_notation(value).map_err(RecordError::InvalidPosition)?;
+                record.start = Some(start);
+            }
+            "WinRule" => win_rule = Some(value.parse().map_err(|_| RecordError::InvalidWinRule(value.clone()))?),
+            "Board" => geometry = Some(value.parse().map_err(|_| RecordError::InvalidBoard(value.clone()))?),
+            _ if name.starts_with("Player") && name["Player".len()..].parse::<usize>().is_ok() => {
+                players.push((name["Player".len()..].parse::<usize>().unwrap(), value.clone()));
+            }
+            _ => record.tags.push((name.clone(), value.clone())),
+        }
+    }
+
+    record.win_rule = win_rule.or(record.start.map(|start| start.win_rule())).unwrap_or_default();
+    record.geometry = geometry.or(record.start.map(|start| start.geometry())).unwrap_or_default();
+    if let Some(start) = record.start {
+        if start.geometry() != record.geometry {
+            return Err(RecordError::InvalidBoard(record.geometry.to_string()));
+        }
+    }
+
+    players.sort();
+    if players.iter().enumerate().any(|(i, &(index, _))| i != index) {
+        return Err(RecordError::InvalidPlayers);
+    }
+    record.players = players.into_iter().map(|(_, name)| name).collect();
+
+    let mut rest = movetext.trim_start();
+    while !rest.is_empty() {
+        if rest.starts_with('{') {
+            let end = comment_end(rest).ok_or(RecordError::UnterminatedComment)?;
+            let comment = rest[1..end].trim();
+            if let Some(last) = record.moves.last_mut() {
+                let (evaluation, comment) = parse_comment(comment);
+                last.evaluation = evaluation;
+                last.comment = if comment.is_empty() { None } else { Some(unescape_comment(comment)) };
+            } else if !comment.is_empty() {
+                record.comment = Some(unescape_comment(comment));
+            }
+            rest = rest[end+1..].trim_start();
+            continue;
+        }
+
+        let end = rest.find(|c: char| c.is_whitespace() || c == '{').unwrap_or(rest.len());
+        let token = &rest[..end];
+        rest = rest[end..].trim_start();
+
+        if token.ends_with('.') && token.trim_end_matches('.').parse::<usize>().is_ok() {
+            continue;
+        }
+
+        if token == "*" && rest.is_empty() {
+            break;
+        }
+
+        let mov = Move::from_str(token).map_err(|err| RecordError::InvalidMove(token.to_owned(), err))?;
+        record.moves.push(MoveRecord { mov, comment: None, evaluation: None });
+    }
+
+    Ok(record)
+}
+
+/// Returns the index of the brace closing the comment `text` starts with.
+fn comment_end(text: &str) -> Option<usize> {
+    let mut chars = text.char_indices().skip(1);
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '}' => return Some(i),
+            _ => {}
+        }
+    }
+
+    None
+}
+
+fn escape_comment(comment: &str) -> String {
+    comment.replace('\\', "\\\\").replace('{', "\\{").replace('}', "\\}")
+}
+
+fn unescape_comment(comment: &str) -> String {
+    let mut result = String::with_capacity(comment.len());
+    let mut chars = comment.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => result.extend(chars.next()),
+            c => result.push(c),
+        }
+    }
+
+    result
+}
+
+/// Splits a comment into its evaluation annotation and the remaining text.
+fn parse_comment(comment: &str) -> (Option<isize>, &str) {
+    if comment.starts_with("[%eval ") {
+        if let Some(end) = comment.find(']') {
+            if let Ok(evaluation) = comment["[%eval ".len()..end].trim().parse() {
+                return (Some(evaluation), comment[end+1..].trim());
+            }
+        }
+    }
+
+    (None, comment)
+}
+
+impl fmt::Display for GameRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(ref event) = self.event {
+            write_tag(f, "Event", event)?;
+        }
+        if let Some(ref date) = self.date {
+            write_tag(f, "Date", date)?;
+        }
+        for (i, player) in self.players.iter().enumerate() {
+            write_tag(f, &format!("Player{}", i), player)?;
+        }
+        if let Some(ref time_control) = self.time_control {
+            write_tag(f, "TimeControl", time_control)?;
+        }
+        if let Some(ref result) = self.result {
+            write_tag(f, "Result", result)?;
+        }
+        if let Some(ref start) = self.start {
+            write_tag(f, "Position", &start.to_notation())?;
+        }
+        if self.win_rule != WinRule::default() || self.start.is_some_and(|start| start.win_rule() != self.win_rule) {
+            write_tag(f, "WinRule", &self.win_rule.to_string())?;
+        }
+        if self.geometry != Geometry::default() {
+            write_tag(f, "Board", &self.geometry.to_string())?;
+        }
+        for (name, value) in &self.tags {
+            write_tag(f, name, value)?;
+        }
+        writeln!(f)?;
+
+        // Replay the game to write jump sequences with their hops. Once a move is illegal we can
+        // not follow the game anymore and fall back to the plain notation.
+        let start = self.start_position().ok();
+        let num_players = ::std::cmp::max(1, self.players.len());
+        // Counts the moves of all players since the start of the first round, so move numbers
+        // continue those of the start position.
+        let first_turn = start.map_or(0, |start| (start.full_move_number() - 1) * num_players + start.current_player() as usize);
+        let mut state = start.unwrap_or_default();
+        let mut legal = start.is_some();
+        let mut tokens = Vec::with_capacity(2*self.moves.len() + 2);
+        if let Some(ref comment) = self.comment {
+            tokens.push(format!("{{{}}}", escape_comment(comment)));
+        }
+        for (i, record) in self.moves.iter().enumerate() {
+            let turn = first_turn + i;
+            let player = turn % num_players;
+            if player == 0 {
+                tokens.push(format!("{}.", turn / num_players + 1));
+            } else if i == 0 {
+                tokens.push(format!("{}...", turn / num_players + 1));
+            }
+
+            legal = legal && state.check_move(record.mov).is_ok();
+            if legal {
+                tokens.push(state.move_to_notation(record.mov));
+                state.move_piece(record.mov);
+            } else {
+                tokens.push(record.mov.to_string());
+            }
+
+            match (record.evaluation, record.comment.as_ref()) {
+                (Some(evaluation), Some(comment)) => tokens.push(format!("{{[%eval {}] {}}}", evaluation, escape_comment(comment))),
+                (Some(evaluation), None) => tokens.push(format!("{{[%eval {}]}}", evaluation)),
+                (None, Some(comment)) => tokens.push(format!("{{{}}}", escape_comment(comment))),
+                (None, None) => {}
+            }
+        }
+        tokens.push("*".to_owned());
+
+        let mut line_len = 0;
+        for token in tokens {
+            if line_len > 0 && line_len + 1 + token.len() > 80 {
+                writeln!(f)?;
+                line_len = 0;
+            } else if line_len > 0 {
+                write!(f, " ")?;
+                line_len += 1;
+            }
+            write!(f, "{}", token)?;
+            line_len += token.len();
+        }
+        writeln!(f)
+    }
+}
+
+impl FromStr for GameRecord {
+    type Err = RecordError;
+
+    fn from_str(s: &str) -> Result<GameRecord, RecordError> {
+        let mut records = read_records(s)?;
+        match records.len() {
+            1 => Ok(records.pop().unwrap()),
+            count => Err(RecordError::RecordCount(count)),
+        }
+    }
+}
+
+mod tests {
+    #[test]
+    fn test_round_trip() {
+        use {Game, GameState, Geometry, WinRule};
+        use record::{GameRecord, RecordError, read_records, write_records};
+
+        let mut records = Vec::new();
+        for &num_players in &[2, 3, 6] {
+            let mut game = Game::new(num_players);
+            for _ in 0..25 {
+                let mov = *game.state().possible_moves().last().unwrap();
+                game.move_piece(mov);
+            }
+
+            let mut record = GameRecord::from_game(&game);
+            record.event = Some("Club \"night\"".to_owned());
+            record.players[0] = "Alice".to_owned();
+            record.moves[3].comment = Some("a {long} jump \\o/".to_owned());
+            record.moves[4].evaluation = Some(-35);
+            record.moves[5].evaluation = Some(120);
+            record.moves[5].comment = Some("best".to_owned());
+            record.tags.push(("Site".to_owned(), "Online".to_owned()));
+            record.comment = Some("A {quiet} game".to_owned());
+            assert_eq!(record.to_game(), Ok(game));
+            records.push(record);
+        }
+
+        let mut start = GameState::new(4);
+        start.set_win_rule(WinRule::NoParking { ply: 40 });
+        let game = Game::from_state(start);
+        records.push(GameRecord::from_game(&game));
+        assert_eq!(records[3].start, None);
+        assert_eq!(records[3].to_game(), Ok(game));
+
+        let mut game = Game::from_state(GameState::new(3));
+        let mov = game.state().possible_moves()[0];
+        game.move_piece(mov);
+        let game = Game::from_state(*game.state());
+        records.push(GameRecord::from_game(&game));
+        assert!(records[4].start.is_some());
+
+        let mut game = Game::from_state(GameState::with_geometry(Geometry::Square, 4));
+        for _ in 0..10 {
+            let mov = game.state().possible_moves()[0];
+            game.move_piece(mov);
+        }
+        records.push(GameRecord::from_game(&game));
+        assert_eq!(records[5].start, None);
+        assert!(records[5].to_string().contains("[Board \"square\"]"));
+        assert_eq!(records[5].to_game(), Ok(game));
+
+        // The start position gives the board and win rule if there are no tags for them.
+        let mut start = GameState::with_geometry(Geometry::Square, 4);
+        let mov = start.possible_moves()[0];
+        start.move_piece(mov);
+        start.set_win_rule(WinRule::NoParking { ply: 30 });
+        let text = format!("[Player0 \"A\"]\n[Player1 \"B\"]\n[Player2 \"C\"]\n[Player3 \"D\"]\n[Position \"{}\"]\n\n{{Played \\{{blind\\}}}} *\n",
+                           start.to_notation());
+        let record: GameRecord = text.parse().unwrap();
+        assert_eq!(record.geometry, Geometry::Square);
+        assert_eq!(record.win_rule, WinRule::NoParking { ply: 30 });
+        assert_eq!(record.comment, Some("Played {blind}".to_owned()));
+        assert_eq!(record.to_game().map(|game| *game.state()), Ok(start));
+        assert_eq!(record.to_string().parse(), Ok(record));
+
+        let text = write_records(&records);
+        assert_eq!(read_records(&text), Ok(records.clone()));
+        assert_eq!(text.parse::<GameRecord>(), Err(RecordError::RecordCount(6)));
+        assert_eq!(records[0].to_string().parse(), Ok(records[0].clone()));
+
+        // Without tags, records are only separated by their termination markers.
+        let mut untagged = records[0].clone();
+        untagged.event = None;
+        untagged.players.clear();
+        untagged.tags.clear();
+        let text = write_records(&[untagged.clone(), untagged.clone()]);
+        assert!(text.lines().all(|line| !line.starts_with('[')));
+        assert_eq!(read_records(&text), Ok(vec![untagged.clone(), untagged]));
+    }
+}